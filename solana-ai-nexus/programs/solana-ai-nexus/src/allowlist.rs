@@ -0,0 +1,156 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use crate::errors::CustomError;
+use crate::state::*;
+
+pub const MAX_ALLOWLIST_ENTRIES: usize = 16;
+
+/// Restricts a private task to the listed agents. Small sets are stored
+/// inline; larger ones are committed to as a Merkle root and agents prove
+/// membership when they are assigned or bid.
+#[account]
+pub struct TaskAllowlist {
+    pub task: Pubkey,
+    pub entries: Vec<AllowlistEntry>,
+    pub merkle_root: Option<[u8; 32]>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum AllowlistEntry {
+    Agent(u64),
+    Owner(Pubkey),
+}
+
+#[derive(Accounts)]
+pub struct SetTaskAllowlist<'info> {
+    #[account(
+        mut,
+        constraint = task.is_manager(authority.key()) @ CustomError::NotTaskManager
+    )]
+    pub task: Account<'info, Task>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + TaskAllowlist::SPACE,
+        seeds = [TaskAllowlist::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub allowlist: Account<'info, TaskAllowlist>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveTaskAllowlist<'info> {
+    #[account(
+        mut,
+        constraint = task.is_manager(authority.key()) @ CustomError::NotTaskManager
+    )]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        seeds = [TaskAllowlist::SEED, task.key().as_ref()],
+        bump = allowlist.bump,
+        close = creator
+    )]
+    pub allowlist: Account<'info, TaskAllowlist>,
+    /// CHECK: receives the allowlist rent; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+    pub authority: Signer<'info>,
+}
+
+impl TaskAllowlist {
+    pub const SEED: &'static [u8] = b"allowlist";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // task
+                            4 + (MAX_ALLOWLIST_ENTRIES * AllowlistEntry::SPACE) + // entries
+                            1 + 32 + // merkle_root
+                            1 + // bump
+                            64; // padding
+
+    /// Whether `agent` is listed inline or `proof` shows it is under the Merkle root.
+    pub fn allows(&self, agent: &Agent, proof: &[[u8; 32]]) -> bool {
+        let entries = [AllowlistEntry::Agent(agent.id), AllowlistEntry::Owner(agent.owner)];
+        entries.iter().any(|entry| {
+            self.entries.contains(entry)
+                || matches!(self.merkle_root, Some(root) if verify_proof(proof, root, entry.leaf()))
+        })
+    }
+}
+
+impl AllowlistEntry {
+    pub const SPACE: usize = 1 + 32;
+
+    /// Merkle leaf for the entry: keccak256 of a kind prefix and its value.
+    pub fn leaf(&self) -> [u8; 32] {
+        match self {
+            AllowlistEntry::Agent(id) => keccak::hashv(&[b"agent", &id.to_le_bytes()]).0,
+            AllowlistEntry::Owner(owner) => keccak::hashv(&[b"owner", owner.as_ref()]).0,
+        }
+    }
+}
+
+/// Verifies a Merkle proof built with sorted-pair keccak256 hashing.
+pub fn verify_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).0
+        } else {
+            keccak::hashv(&[sibling, &node]).0
+        }
+    });
+    computed == root
+}
+
+/// Checks that `agent` may take a private task. `allowlist` is the task's
+/// allowlist PDA; it is only read when the task is private.
+pub fn check_allowlist(
+    task: &Task,
+    allowlist: &AccountInfo,
+    agent: &Agent,
+    proof: &[[u8; 32]],
+) -> Result<()> {
+    if !task.is_private {
+        return Ok(());
+    }
+    let allowlist = Account::<TaskAllowlist>::try_from(allowlist)?;
+    require!(allowlist.allows(agent, proof), CustomError::AgentNotAllowlisted);
+    Ok(())
+}
+
+/// Makes a pending task private, replacing any existing allowlist.
+pub fn set_task_allowlist(
+    ctx: Context<SetTaskAllowlist>,
+    entries: Vec<AllowlistEntry>,
+    merkle_root: Option<[u8; 32]>,
+) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+
+    require!(task.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+    require!(entries.len() <= MAX_ALLOWLIST_ENTRIES, CustomError::InvalidAllowlist);
+    require!(!entries.is_empty() || merkle_root.is_some(), CustomError::InvalidAllowlist);
+
+    let allowlist = &mut ctx.accounts.allowlist;
+    allowlist.task = task.key();
+    allowlist.entries = entries;
+    allowlist.merkle_root = merkle_root;
+    allowlist.bump = *ctx.bumps.get("allowlist").unwrap();
+
+    task.is_private = true;
+    Ok(())
+}
+
+/// Closes the task's allowlist and returns its rent. On a pending task this
+/// opens it back up to every agent; once the task has been assigned the
+/// allowlist is no longer read and is simply reclaimed.
+pub fn remove_task_allowlist(ctx: Context<RemoveTaskAllowlist>) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+
+    if task.status == TaskStatus::Pending {
+        task.is_private = false;
+    }
+    Ok(())
+}
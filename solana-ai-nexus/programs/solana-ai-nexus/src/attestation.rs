@@ -0,0 +1,169 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::solana_program::{blake3, ed25519_program, hash};
+use crate::errors::CustomError;
+use crate::state::*;
+
+// Layout of the ed25519 precompile's instruction data: a signature count and
+// padding byte, then one offsets record of seven u16 values per signature.
+const ED25519_HEADER_LEN: usize = 2;
+const ED25519_OFFSETS_LEN: usize = 14;
+const ED25519_CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum HashKind {
+    Sha256,
+    Blake3,
+}
+
+#[derive(Accounts)]
+pub struct VerifyResult<'info> {
+    pub task: Account<'info, Task>,
+}
+
+#[event]
+pub struct ResultCommitted {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub result_hash: [u8; 32],
+    pub hash_kind: HashKind,
+    pub attested: bool,
+    pub timestamp: i64,
+}
+
+impl HashKind {
+    /// Hashes fetched result content so it can be compared with `Task::result_hash`.
+    pub fn hash(&self, content: &[u8]) -> [u8; 32] {
+        match self {
+            HashKind::Sha256 => hash::hash(content).to_bytes(),
+            HashKind::Blake3 => blake3::hash(content).to_bytes(),
+        }
+    }
+}
+
+impl Task {
+    /// Whether `content` is the result the agent committed to.
+    pub fn result_matches(&self, content: &[u8]) -> bool {
+        self.result_uri.is_some() && self.result_hash_kind.hash(content) == self.result_hash
+    }
+}
+
+/// The message an attestation key signs for a result: the task address
+/// followed by the result hash.
+pub fn attestation_message(task: &Pubkey, result_hash: &[u8; 32]) -> [u8; 64] {
+    let mut message = [0u8; 64];
+    message[..32].copy_from_slice(task.as_ref());
+    message[32..].copy_from_slice(result_hash);
+    message
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16> {
+    let bytes = data.get(at..at + 2).ok_or(CustomError::InvalidAttestation)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Checks that the instruction before the current one is an ed25519
+/// precompile verification of `message` signed by `signer`. The precompile
+/// rejects the transaction if the signature is invalid, so only the key and
+/// message it checked need to be matched here.
+pub fn verify_ed25519_instruction(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    require!(current > 0, CustomError::InvalidAttestation);
+    let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+    require!(ix.program_id == ed25519_program::ID, CustomError::InvalidAttestation);
+
+    let data = &ix.data;
+    require!(
+        data.len() >= ED25519_HEADER_LEN + ED25519_OFFSETS_LEN && data[0] == 1,
+        CustomError::InvalidAttestation
+    );
+    let offsets = ED25519_HEADER_LEN;
+    let signature_ix = read_u16(data, offsets + 2)?;
+    let public_key_offset = read_u16(data, offsets + 4)? as usize;
+    let public_key_ix = read_u16(data, offsets + 6)?;
+    let message_offset = read_u16(data, offsets + 8)? as usize;
+    let message_size = read_u16(data, offsets + 10)? as usize;
+    let message_ix = read_u16(data, offsets + 12)?;
+    require!(
+        signature_ix == ED25519_CURRENT_INSTRUCTION
+            && public_key_ix == ED25519_CURRENT_INSTRUCTION
+            && message_ix == ED25519_CURRENT_INSTRUCTION,
+        CustomError::InvalidAttestation
+    );
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(CustomError::InvalidAttestation)?;
+    let signed_message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(CustomError::InvalidAttestation)?;
+    require!(
+        public_key == signer.as_ref() && signed_message == message,
+        CustomError::InvalidAttestation
+    );
+    Ok(())
+}
+
+/// Checks that the agent's attestation key signed `result_hash` for `task`
+/// in the preceding ed25519 instruction.
+pub fn check_attestation(
+    instructions: &AccountInfo,
+    agent: &Agent,
+    task: &Pubkey,
+    result_hash: &[u8; 32],
+) -> Result<()> {
+    let attestation_key = agent.attestation_key.ok_or(CustomError::NoAttestationKey)?;
+    verify_ed25519_instruction(
+        instructions,
+        &attestation_key,
+        &attestation_message(task, result_hash),
+    )
+}
+
+/// Records the content hash for a submitted result. When `attested` is set
+/// the agent's attestation key must have signed it in a preceding ed25519
+/// instruction.
+pub fn commit_result(
+    task: &mut Account<Task>,
+    agent: &Agent,
+    result_hash: [u8; 32],
+    hash_kind: HashKind,
+    attested: bool,
+    instructions: &AccountInfo,
+) -> Result<()> {
+    if attested {
+        check_attestation(instructions, agent, &task.key(), &result_hash)?;
+    }
+
+    task.result_hash = result_hash;
+    task.result_hash_kind = hash_kind;
+    task.result_attested = attested;
+
+    emit!(ResultCommitted {
+        task_id: task.id,
+        agent_id: agent.id,
+        result_hash,
+        hash_kind,
+        attested,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// View instruction: fails unless `content_hash` matches the committed result.
+/// Clients hash the fetched content with the task's `result_hash_kind` and
+/// simulate this to check it.
+pub fn verify_result(ctx: Context<VerifyResult>, content_hash: [u8; 32]) -> Result<()> {
+    let task = &ctx.accounts.task;
+
+    require!(task.result_uri.is_some(), CustomError::InvalidTaskStatus);
+    require!(content_hash == task.result_hash, CustomError::ResultHashMismatch);
+    Ok(())
+}
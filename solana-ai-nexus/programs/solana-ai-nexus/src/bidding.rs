@@ -0,0 +1,290 @@
+use anchor_lang::prelude::*;
+use crate::allowlist::*;
+use crate::errors::CustomError;
+use crate::state::*;
+use crate::transfer_lamports;
+
+pub const MAX_PROPOSAL_URI_LEN: usize = 128;
+
+#[account]
+pub struct Bid {
+    pub task: Pubkey,
+    pub agent_id: u64,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub eta: i64, // Seconds the agent needs once assigned
+    pub proposal_uri: String,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct SubmitBid<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    pub task: Account<'info, Task>,
+    #[account(constraint = agent.is_operator(bidder.key()) @ CustomError::NotAgentOperator)]
+    pub agent: Account<'info, Agent>,
+    /// CHECK: the task's allowlist; only read when the task is private.
+    #[account(seeds = [TaskAllowlist::SEED, task.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + Bid::SPACE,
+        seeds = [Bid::SEED, task.key().as_ref(), agent.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBid<'info> {
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        has_one = bidder @ CustomError::Unauthorized,
+        constraint = bid.task == task.key() @ CustomError::BidTaskMismatch,
+        close = bidder
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBid<'info> {
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = bid.task == task.key() @ CustomError::BidTaskMismatch,
+        close = bidder
+    )]
+    pub bid: Account<'info, Bid>,
+    /// CHECK: receives the bid rent; address is pinned to the bidder.
+    #[account(mut, address = bid.bidder @ CustomError::Unauthorized)]
+    pub bidder: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBid<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        constraint = task.is_manager(authority.key()) @ CustomError::NotTaskManager
+    )]
+    pub task: Account<'info, Task>,
+    #[account(mut, constraint = agent.id == bid.agent_id @ CustomError::AgentIdMismatch)]
+    pub agent: Account<'info, Agent>,
+    /// CHECK: the task's allowlist; only read when the task is private.
+    #[account(seeds = [TaskAllowlist::SEED, task.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = bid.task == task.key() @ CustomError::BidTaskMismatch,
+        close = bidder
+    )]
+    pub bid: Account<'info, Bid>,
+    /// CHECK: receives the bid rent; address is pinned to the bidder.
+    #[account(mut, address = bid.bidder @ CustomError::Unauthorized)]
+    pub bidder: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the unused reward; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+    pub authority: Signer<'info>,
+}
+
+impl Bid {
+    pub const SEED: &'static [u8] = b"bid";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // task
+                            8 + // agent_id
+                            32 + // bidder
+                            8 + // price
+                            8 + // eta
+                            4 + MAX_PROPOSAL_URI_LEN + // proposal_uri
+                            8 + // created_at
+                            1 + // bump
+                            64; // padding
+}
+
+#[event]
+pub struct BidSubmitted {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub eta: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidWithdrawn {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub bidder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidClosed {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub bidder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidAccepted {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub price: u64,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+pub fn submit_bid(
+    ctx: Context<SubmitBid>,
+    price: u64,
+    eta: i64,
+    proposal_uri: String,
+    proof: Vec<[u8; 32]>,
+) -> Result<()> {
+    let task = &ctx.accounts.task;
+    let agent = &ctx.accounts.agent;
+    let bid = &mut ctx.accounts.bid;
+    let clock = Clock::get()?;
+
+    require!(agent.is_active, CustomError::AgentNotActive);
+    require!(task.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+    require!(!task.has_milestones(), CustomError::TaskHasMilestones);
+    require!(
+        agent.has_capabilities(task.required_capabilities),
+        CustomError::MissingCapabilities
+    );
+    require!(price > 0 && price <= task.reward, CustomError::InvalidBidPrice);
+    require!(eta > 0, CustomError::InvalidBidEta);
+    check_allowlist(task, &ctx.accounts.allowlist, agent, &proof)?;
+    require!(proposal_uri.len() <= MAX_PROPOSAL_URI_LEN, CustomError::ProposalUriTooLong);
+
+    bid.task = task.key();
+    bid.agent_id = agent.id;
+    bid.bidder = ctx.accounts.bidder.key();
+    bid.price = price;
+    bid.eta = eta;
+    bid.proposal_uri = proposal_uri;
+    bid.created_at = clock.unix_timestamp;
+    bid.bump = *ctx.bumps.get("bid").unwrap();
+
+    emit!(BidSubmitted {
+        task_id: task.id,
+        agent_id: agent.id,
+        bidder: bid.bidder,
+        price,
+        eta,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Lets the bidder pull a bid while the task is still open for bids.
+pub fn withdraw_bid(ctx: Context<WithdrawBid>) -> Result<()> {
+    let task = &ctx.accounts.task;
+    let bid = &ctx.accounts.bid;
+
+    require!(task.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+
+    emit!(BidWithdrawn {
+        task_id: task.id,
+        agent_id: bid.agent_id,
+        bidder: bid.bidder,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Closes a losing bid once the task has left `Pending`, returning its rent
+/// to the bidder. Anyone may call it.
+pub fn close_bid(ctx: Context<CloseBid>) -> Result<()> {
+    let task = &ctx.accounts.task;
+    let bid = &ctx.accounts.bid;
+
+    require!(task.status != TaskStatus::Pending, CustomError::InvalidTaskStatus);
+
+    emit!(BidClosed {
+        task_id: task.id,
+        agent_id: bid.agent_id,
+        bidder: bid.bidder,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Assigns the task to the winning bid and refunds the creator the difference
+/// between the reward and the bid price. Losing bids may be passed as
+/// `(bid, bidder)` pairs in the remaining accounts to be closed in the same
+/// instruction; any left over can be closed later with `close_bid`. Private
+/// tasks re-check the allowlist in case it changed since the bid was placed.
+pub fn accept_bid<'info>(
+    ctx: Context<'_, '_, '_, 'info, AcceptBid<'info>>,
+    proof: Vec<[u8; 32]>,
+) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+    let agent = &mut ctx.accounts.agent;
+    let escrow = &mut ctx.accounts.escrow;
+    let price = ctx.accounts.bid.price;
+
+    require!(!task.has_milestones(), CustomError::TaskHasMilestones);
+    check_allowlist(task, &ctx.accounts.allowlist, agent, &proof)?;
+
+    let refunded = task.reward.checked_sub(price).unwrap();
+    task.reward = price;
+    task.assign(agent, &ctx.accounts.state)?;
+
+    escrow.amount = escrow.amount.checked_sub(refunded).unwrap();
+    transfer_lamports(
+        &escrow.to_account_info(),
+        &ctx.accounts.creator.to_account_info(),
+        refunded,
+    )?;
+
+    require!(ctx.remaining_accounts.chunks_exact(2).remainder().is_empty(), CustomError::BidTaskMismatch);
+    for pair in ctx.remaining_accounts.chunks(2) {
+        require!(pair[0].key() != ctx.accounts.bid.key(), CustomError::BidTaskMismatch);
+        let losing_bid = Account::<Bid>::try_from(&pair[0])?;
+        require!(losing_bid.task == task.key(), CustomError::BidTaskMismatch);
+        require!(losing_bid.bidder == pair[1].key(), CustomError::Unauthorized);
+        losing_bid.close(pair[1].clone())?;
+    }
+
+    emit!(BidAccepted {
+        task_id: task.id,
+        agent_id: agent.id,
+        price,
+        refunded,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -1,10 +1,14 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token::{Mint, Token, TokenAccount};
 use crate::state::*;
+use crate::errors::CustomError;
+use crate::allowlist::TaskAllowlist;
+use crate::milestones::MilestonePlan;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
-    #[account(init, payer = authority, space = 8 + State::SPACE)]
+    #[account(init, payer = authority, space = 8 + State::SPACE, seeds = [State::SEED], bump)]
     pub state: Account<'info, State>,
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -13,8 +17,20 @@ pub struct Initialize<'info> {
 
 #[derive(Accounts)]
 pub struct RegisterAgent<'info> {
+    #[account(
+        mut,
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
     pub state: Account<'info, State>,
-    #[account(init, payer = owner, space = 8 + Agent::SPACE)]
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Agent::SPACE,
+        seeds = [Agent::SEED, &state.agent_count.to_le_bytes()],
+        bump
+    )]
     pub agent: Account<'info, Agent>,
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -23,53 +39,279 @@ pub struct RegisterAgent<'info> {
 
 #[derive(Accounts)]
 pub struct CreateTask<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
     pub state: Account<'info, State>,
-    #[account(init, payer = creator, space = 8 + Task::SPACE)]
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Task::SPACE,
+        seeds = [Task::SEED, &state.task_count.to_le_bytes()],
+        bump
+    )]
     pub task: Account<'info, Task>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + TaskEscrow::SPACE,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + RateLimit::SPACE,
+        seeds = [RateLimit::SEED, creator.key().as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
     #[account(mut)]
     pub creator: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
+#[instruction(agent_id: u64)]
 pub struct AssignTask<'info> {
-    #[account(mut)]
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        constraint = task.is_manager(authority.key()) @ CustomError::NotTaskManager
+    )]
     pub task: Account<'info, Task>,
+    #[account(mut, constraint = agent.id == agent_id @ CustomError::AgentIdMismatch)]
     pub agent: Account<'info, Agent>,
+    /// CHECK: the task's allowlist; only read when the task is private.
+    #[account(seeds = [TaskAllowlist::SEED, task.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + RateLimit::SPACE,
+        seeds = [RateLimit::SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
+    #[account(mut)]
     pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct CompleteTask<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
     #[account(mut)]
     pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch,
+        constraint = agent.is_operator(authority.key()) @ CustomError::NotAgentOperator
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + RateLimit::SPACE,
+        seeds = [RateLimit::SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: the instructions sysvar, read for the ed25519 attestation.
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTask<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAssignedTask<'info> {
     #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch,
+        constraint = agent.is_operator(authority.key()) @ CustomError::NotAgentOperator
+    )]
     pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the refund; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct ExpireTask<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the refund; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+    /// CHECK: the agent's stake vault; only read when there is stake to slash.
+    #[account(mut, seeds = [Agent::STAKE_SEED, agent.key().as_ref()], bump)]
+    pub stake_account: UncheckedAccount<'info>,
+    // Only needed when the agent has stake to slash; checked against the vault's mint then.
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == task.creator @ CustomError::InvalidSlashDestination
+    )]
+    pub creator_token_account: Option<Account<'info, TokenAccount>>,
+    // Only needed when part of the slash goes to the treasury.
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == state.treasury @ CustomError::InvalidSlashDestination
+    )]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    // Required for milestone tasks so milestones submitted on time are paid out.
+    #[account(mut, constraint = plan.task == task.key() @ CustomError::InvalidMilestones)]
+    pub plan: Option<Account<'info, MilestonePlan>>,
+    /// CHECK: receives on-time milestone payments; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SetTaskDelegate<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageAgent<'info> {
+    #[account(mut, has_one = owner @ CustomError::Unauthorized)]
+    pub agent: Account<'info, Agent>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAgentOwnership<'info> {
+    #[account(
+        mut,
+        constraint = agent.pending_owner == Some(new_owner.key()) @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
+    pub new_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAgent<'info> {
+    #[account(mut, has_one = owner @ CustomError::Unauthorized, close = owner)]
+    pub agent: Account<'info, Agent>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct StakeTokens<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut, constraint = agent.owner == staker.key() @ CustomError::Unauthorized)]
+    pub agent: Account<'info, Agent>,
     #[account(mut)]
     pub staker: Signer<'info>,
     #[account(mut)]
     pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = staker,
+        seeds = [Agent::STAKE_SEED, agent.key().as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = agent
+    )]
+    pub stake_account: Account<'info, TokenAccount>,
+    #[account(address = state.stake_mint @ CustomError::InvalidStakeMint)]
+    pub stake_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeTokens<'info> {
+    #[account(mut, constraint = agent.owner == staker.key() @ CustomError::Unauthorized)]
+    pub agent: Account<'info, Agent>,
+    pub staker: Signer<'info>,
     #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [Agent::STAKE_SEED, agent.key().as_ref()],
+        bump
+    )]
     pub stake_account: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
+#[instruction(agent_id: u64)]
 pub struct UpdateReputation<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
     pub state: Account<'info, State>,
-    #[account(mut)]
+    #[account(mut, constraint = agent.id == agent_id @ CustomError::AgentIdMismatch)]
     pub agent: Account<'info, Agent>,
     pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
 pub struct AdminOnly<'info> {
+    #[account(mut, seeds = [State::SEED], bump = state.bump)]
     pub state: Account<'info, State>,
     pub authority: Signer<'info>,
 }
@@ -1 +1,392 @@
- 
\ No newline at end of file
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Token, TokenAccount};
+use crate::errors::CustomError;
+use crate::reputation::*;
+use crate::slashing::*;
+use crate::state::*;
+use crate::team::*;
+use crate::transfer_lamports;
+
+pub const MAX_EVIDENCE: usize = 8;
+pub const MAX_EVIDENCE_URI_LEN: usize = 128;
+pub const DEFAULT_DISPUTE_WINDOW: i64 = 3 * 24 * 60 * 60;
+
+#[account]
+pub struct Dispute {
+    pub task: Pubkey,
+    pub opened_by: Pubkey,
+    pub reason_uri: String,
+    pub evidence: Vec<Evidence>,
+    pub status: DisputeStatus,
+    pub creator_share_bps: u16,
+    pub opened_at: i64,
+    pub resolved_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Evidence {
+    pub submitter: Pubkey,
+    pub uri: String,
+    pub hash: [u8; 32],
+    pub submitted_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum DisputeStatus {
+    Open,
+    Resolved,
+}
+
+#[derive(Accounts)]
+pub struct SetDisputeConfig<'info> {
+    #[account(
+        mut,
+        seeds = [State::SEED],
+        bump = state.bump,
+        has_one = authority @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch)]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        init,
+        payer = opener,
+        space = 8 + Dispute::SPACE,
+        seeds = [Dispute::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+    #[account(mut)]
+    pub opener: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitEvidence<'info> {
+    pub task: Account<'info, Task>,
+    #[account(constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch)]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [Dispute::SEED, task.key().as_ref()],
+        bump = dispute.bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+    pub submitter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        has_one = arbiter @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [Dispute::SEED, task.key().as_ref()],
+        bump = dispute.bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives its share of the reward; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+    /// CHECK: receives its share of the reward; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: UncheckedAccount<'info>,
+    /// CHECK: the agent's stake vault; only read when there is stake to slash.
+    #[account(mut, seeds = [Agent::STAKE_SEED, agent.key().as_ref()], bump)]
+    pub stake_account: UncheckedAccount<'info>,
+    // Only needed when the agent has stake to slash; checked against the vault's mint then.
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == task.creator @ CustomError::InvalidSlashDestination
+    )]
+    pub creator_token_account: Option<Account<'info, TokenAccount>>,
+    // Only needed when part of the slash goes to the treasury.
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == state.treasury @ CustomError::InvalidSlashDestination
+    )]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub arbiter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDispute<'info> {
+    #[account(mut, close = opened_by)]
+    pub dispute: Account<'info, Dispute>,
+    /// CHECK: receives the dispute rent; address is pinned to whoever opened it.
+    #[account(mut, address = dispute.opened_by @ CustomError::Unauthorized)]
+    pub opened_by: UncheckedAccount<'info>,
+}
+
+impl Dispute {
+    pub const SEED: &'static [u8] = b"dispute";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // task
+                            32 + // opened_by
+                            4 + MAX_EVIDENCE_URI_LEN + // reason_uri
+                            4 + (MAX_EVIDENCE * Evidence::SPACE) + // evidence
+                            1 + // status
+                            2 + // creator_share_bps
+                            8 + // opened_at
+                            8 + // resolved_at
+                            1 + // bump
+                            64; // padding
+}
+
+impl Evidence {
+    pub const SPACE: usize = 32 + // submitter
+                            4 + MAX_EVIDENCE_URI_LEN + // uri
+                            32 + // hash
+                            8; // submitted_at
+}
+
+#[event]
+pub struct DisputeOpened {
+    pub task_id: u64,
+    pub opened_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EvidenceSubmitted {
+    pub task_id: u64,
+    pub submitter: Pubkey,
+    pub hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeClosed {
+    pub task: Pubkey,
+    pub opened_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub task_id: u64,
+    pub arbiter: Pubkey,
+    pub creator_share_bps: u16,
+    pub creator_amount: u64,
+    pub agent_amount: u64,
+    pub timestamp: i64,
+}
+
+fn is_party(task: &Task, agent: &Agent, key: Pubkey) -> bool {
+    key == task.creator || key == agent.owner
+}
+
+// The arbiter may be a single key or a multisig held by an appointed panel.
+pub fn set_dispute_config(
+    ctx: Context<SetDisputeConfig>,
+    arbiter: Pubkey,
+    dispute_window: i64,
+) -> Result<()> {
+    require!(dispute_window >= 0, CustomError::InvalidDisputeWindow);
+
+    let state = &mut ctx.accounts.state;
+    state.arbiter = arbiter;
+    state.dispute_window = dispute_window;
+    Ok(())
+}
+
+pub fn open_dispute(ctx: Context<OpenDispute>, reason_uri: String) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+    let dispute = &mut ctx.accounts.dispute;
+    let opener = ctx.accounts.opener.key();
+    let clock = Clock::get()?;
+
+    require!(
+        is_party(task, &ctx.accounts.agent, opener),
+        CustomError::Unauthorized
+    );
+    require!(task.status == TaskStatus::Rejected, CustomError::InvalidTaskStatus);
+    require!(
+        clock.unix_timestamp <= task.rejected_at.checked_add(ctx.accounts.state.dispute_window).unwrap(),
+        CustomError::DisputeWindowClosed
+    );
+    require!(reason_uri.len() <= MAX_EVIDENCE_URI_LEN, CustomError::EvidenceUriTooLong);
+
+    dispute.task = task.key();
+    dispute.opened_by = opener;
+    dispute.reason_uri = reason_uri;
+    dispute.evidence = Vec::new();
+    dispute.status = DisputeStatus::Open;
+    dispute.creator_share_bps = 0;
+    dispute.opened_at = clock.unix_timestamp;
+    dispute.resolved_at = 0;
+    dispute.bump = *ctx.bumps.get("dispute").unwrap();
+
+    task.status = TaskStatus::Disputed;
+
+    emit!(DisputeOpened {
+        task_id: task.id,
+        opened_by: opener,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn submit_evidence(ctx: Context<SubmitEvidence>, uri: String, hash: [u8; 32]) -> Result<()> {
+    let dispute = &mut ctx.accounts.dispute;
+    let submitter = ctx.accounts.submitter.key();
+    let clock = Clock::get()?;
+
+    require!(
+        is_party(&ctx.accounts.task, &ctx.accounts.agent, submitter),
+        CustomError::Unauthorized
+    );
+    require!(dispute.status == DisputeStatus::Open, CustomError::InvalidDisputeStatus);
+    require!(dispute.evidence.len() < MAX_EVIDENCE, CustomError::EvidenceLimitReached);
+    require!(uri.len() <= MAX_EVIDENCE_URI_LEN, CustomError::EvidenceUriTooLong);
+
+    dispute.evidence.push(Evidence {
+        submitter,
+        uri,
+        hash,
+        submitted_at: clock.unix_timestamp,
+    });
+
+    emit!(EvidenceSubmitted {
+        task_id: ctx.accounts.task.id,
+        submitter,
+        hash,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// The agent's share of a team task is split across the team like a normal
+/// payout, with the team and `(agent, owner)` pairs passed as remaining
+/// accounts; only the lead is penalized and slashed.
+pub fn resolve_dispute<'info>(
+    ctx: Context<'_, '_, '_, 'info, ResolveDispute<'info>>,
+    creator_share_bps: u16,
+) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+    let agent = &mut ctx.accounts.agent;
+    let dispute = &mut ctx.accounts.dispute;
+    let escrow = &mut ctx.accounts.escrow;
+    let clock = Clock::get()?;
+
+    require!(dispute.status == DisputeStatus::Open, CustomError::InvalidDisputeStatus);
+    require!(task.status == TaskStatus::Disputed, CustomError::InvalidTaskStatus);
+    require!(creator_share_bps as u64 <= BPS_DENOMINATOR, CustomError::InvalidShare);
+
+    // Split the escrowed reward by ruling; the remaining rent goes back to the creator on close.
+    let creator_amount = (escrow.amount as u128)
+        .checked_mul(creator_share_bps as u128)
+        .unwrap()
+        .checked_div(BPS_DENOMINATOR as u128)
+        .unwrap() as u64;
+    let agent_amount = escrow.amount.checked_sub(creator_amount).unwrap();
+    escrow.amount = 0;
+
+    let team_paid = if task.has_team() {
+        pay_team_members(
+            task.key(),
+            &escrow.to_account_info(),
+            agent_amount,
+            ctx.remaining_accounts,
+            clock.unix_timestamp,
+        )?
+    } else {
+        0
+    };
+    let lead_amount = agent_amount.checked_sub(team_paid).unwrap();
+    transfer_lamports(
+        &escrow.to_account_info(),
+        &ctx.accounts.agent_owner.to_account_info(),
+        lead_amount,
+    )?;
+
+    // The agent is credited for what it was paid and loses reputation in
+    // proportion to the share awarded to the creator.
+    if lead_amount > 0 {
+        agent.tasks_completed = agent.tasks_completed.checked_add(1).unwrap();
+        apply_outcome(agent, ReputationOutcome::Completed { reward: lead_amount }, clock.unix_timestamp)?;
+    }
+    if creator_share_bps > 0 {
+        apply_outcome(agent, ReputationOutcome::DisputeLost { creator_share_bps }, clock.unix_timestamp)?;
+    }
+
+    agent.open_tasks = agent.open_tasks.saturating_sub(1);
+
+    // Bonded stake is slashed in the same proportion.
+    slash_agent_stake(
+        &ctx.accounts.state,
+        agent,
+        task,
+        creator_share_bps as u64,
+        SlashAccounts {
+            stake_account: &ctx.accounts.stake_account.to_account_info(),
+            creator_token_account: ctx.accounts.creator_token_account.as_ref(),
+            treasury_token_account: ctx.accounts.treasury_token_account.as_ref(),
+            token_program: &ctx.accounts.token_program.to_account_info(),
+        },
+        SlashReason::LostDispute,
+    )?;
+
+    dispute.status = DisputeStatus::Resolved;
+    dispute.creator_share_bps = creator_share_bps;
+    dispute.resolved_at = clock.unix_timestamp;
+    task.status = TaskStatus::Resolved;
+
+    emit!(DisputeResolved {
+        task_id: task.id,
+        arbiter: ctx.accounts.arbiter.key(),
+        creator_share_bps,
+        creator_amount,
+        agent_amount,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Closes a resolved dispute and returns its rent to whoever opened it. The
+/// ruling stays on record in the `DisputeResolved` event. Anyone may call it.
+pub fn close_dispute(ctx: Context<CloseDispute>) -> Result<()> {
+    let dispute = &ctx.accounts.dispute;
+
+    require!(dispute.status == DisputeStatus::Resolved, CustomError::InvalidDisputeStatus);
+
+    emit!(DisputeClosed {
+        task: dispute.task,
+        opened_by: dispute.opened_by,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
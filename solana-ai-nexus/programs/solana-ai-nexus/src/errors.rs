@@ -34,4 +34,154 @@ pub enum CustomError {
     TransactionExpired,
     #[msg("Slippage exceeded")]
     SlippageExceeded,
-}
\ No newline at end of file
+    #[msg("Task reward must be greater than zero")]
+    InvalidReward,
+    #[msg("Task deadline must be in the future")]
+    InvalidDeadline,
+    #[msg("Task deadline has passed")]
+    TaskDeadlinePassed,
+    #[msg("Task deadline has not been reached")]
+    TaskDeadlineNotReached,
+    #[msg("Escrow balance is insufficient")]
+    InsufficientEscrow,
+    #[msg("Agent is not assigned to this task")]
+    AgentTaskMismatch,
+    #[msg("Agent account does not match the requested agent id")]
+    AgentIdMismatch,
+    #[msg("Only the task creator or its delegate may do this")]
+    NotTaskManager,
+    #[msg("Only the agent owner or its operator may do this")]
+    NotAgentOperator,
+    #[msg("Agent still has open tasks")]
+    AgentHasOpenTasks,
+    #[msg("Agent name is too long")]
+    NameTooLong,
+    #[msg("Description is too long")]
+    DescriptionTooLong,
+    #[msg("Metadata URI is too long")]
+    MetadataUriTooLong,
+    #[msg("Result URI is too long")]
+    ResultUriTooLong,
+    #[msg("Stake mint does not match the protocol stake mint")]
+    InvalidStakeMint,
+    #[msg("Slashed stake destination is invalid")]
+    InvalidSlashDestination,
+    #[msg("Task has already been rated")]
+    TaskAlreadyRated,
+    #[msg("Rating is out of range")]
+    InvalidRating,
+    #[msg("Override reason is missing or too long")]
+    InvalidOverrideReason,
+    #[msg("Bid does not belong to this task")]
+    BidTaskMismatch,
+    #[msg("Bid price must be positive and at most the task reward")]
+    InvalidBidPrice,
+    #[msg("Bid ETA must be positive")]
+    InvalidBidEta,
+    #[msg("Proposal URI is too long")]
+    ProposalUriTooLong,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("Dispute window must not be negative")]
+    InvalidDisputeWindow,
+    #[msg("Invalid dispute status")]
+    InvalidDisputeStatus,
+    #[msg("Evidence limit reached")]
+    EvidenceLimitReached,
+    #[msg("Evidence URI is too long")]
+    EvidenceUriTooLong,
+    #[msg("Share must not exceed 10000 basis points")]
+    InvalidShare,
+    #[msg("Protocol is paused")]
+    ProtocolPaused,
+    #[msg("Rate limit window and action count must be greater than zero")]
+    InvalidRateLimit,
+    #[msg("Milestone amounts must add up to the reward with increasing deadlines")]
+    InvalidMilestones,
+    #[msg("Milestone index out of range")]
+    InvalidMilestoneIndex,
+    #[msg("Invalid milestone status")]
+    InvalidMilestoneStatus,
+    #[msg("Task is paid out by milestone")]
+    TaskHasMilestones,
+    #[msg("Review window has closed")]
+    ReviewWindowClosed,
+    #[msg("Review window is still open")]
+    ReviewWindowOpen,
+    #[msg("Review window cannot be negative")]
+    InvalidReviewWindow,
+    #[msg("Rejection URI too long")]
+    RejectionUriTooLong,
+    #[msg("Team must have distinct members whose accounts match the member list")]
+    InvalidTeam,
+    #[msg("Task is shared by a team")]
+    TaskHasTeam,
+    #[msg("Interval and run duration must be greater than zero")]
+    InvalidInterval,
+    #[msg("Max runs must be greater than zero")]
+    InvalidMaxRuns,
+    #[msg("Recurring task is paused")]
+    RecurringTaskPaused,
+    #[msg("Recurring task has spawned all of its runs")]
+    RecurringRunsExhausted,
+    #[msg("Next run is not due yet")]
+    RecurringRunNotDue,
+    #[msg("Unknown capability flags")]
+    InvalidCapabilities,
+    #[msg("Agent lacks the capabilities the task requires")]
+    MissingCapabilities,
+    #[msg("Allowlist needs inline entries or a Merkle root, within the entry limit")]
+    InvalidAllowlist,
+    #[msg("Agent is not on the task's allowlist")]
+    AgentNotAllowlisted,
+    #[msg("Agent has no attestation key")]
+    NoAttestationKey,
+    #[msg("Missing or invalid ed25519 attestation instruction")]
+    InvalidAttestation,
+    #[msg("Content does not match the committed result hash")]
+    ResultHashMismatch,
+    #[msg("Invalid listing status")]
+    InvalidListingStatus,
+    #[msg("Marketplace is not active")]
+    MarketplaceInactive,
+    #[msg("Token account is not for the marketplace payment mint")]
+    InvalidPaymentMint,
+    #[msg("Price must be greater than zero")]
+    InvalidPrice,
+    #[msg("Fee cannot exceed 100%")]
+    InvalidFee,
+    #[msg("Agent is locked by a marketplace listing")]
+    AgentListed,
+    #[msg("Listing is not of the required type")]
+    InvalidListingType,
+    #[msg("Rental period and duration must be greater than zero")]
+    InvalidRentalTerms,
+    #[msg("Rental duration exceeds the listing's maximum")]
+    RentalTooLong,
+    #[msg("Agent is rented out")]
+    AgentRented,
+    #[msg("Rental has not expired yet")]
+    RentalNotExpired,
+    #[msg("Offer must be below the list price")]
+    OfferTooHigh,
+    #[msg("Auction needs a future end time, a positive bid increment and a non-negative extension")]
+    InvalidAuctionTerms,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("Bid is below the reserve or minimum increment")]
+    BidTooLow,
+    #[msg("Auction already has bids")]
+    AuctionHasBids,
+    #[msg("The highest bid cannot be refunded")]
+    BidIsWinning,
+    #[msg("Bid is not the highest bid")]
+    NotWinningBid,
+    #[msg("Agent still has bonded stake")]
+    StakeStillBonded,
+    #[msg("Seller cannot bid on their own listing")]
+    SellerCannotBid,
+}
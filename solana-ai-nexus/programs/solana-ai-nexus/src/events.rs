@@ -3,6 +3,15 @@ use anchor_lang::prelude::*;
 #[event]
 pub struct StakeEvent {
     pub staker: Pubkey,
+    pub agent_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnstakeEvent {
+    pub staker: Pubkey,
+    pub agent_id: u64,
     pub amount: u64,
     pub timestamp: i64,
 }
@@ -15,6 +24,28 @@ pub struct ReputationUpdateEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct AgentOwnershipTransferredEvent {
+    pub agent_id: u64,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TaskExpiredEvent {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TaskCancelledEvent {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct ProposalCreatedEvent {
     pub proposal_id: u64,
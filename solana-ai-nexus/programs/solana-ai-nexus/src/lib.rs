@@ -1,19 +1,46 @@
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::token::{self};
 
-mod state;
-mod contexts;
-mod errors;
-mod events;
+pub mod state;
+pub mod contexts;
+pub mod errors;
+pub mod events;
+pub mod dispute;
+pub mod slashing;
+pub mod reputation;
+pub mod bidding;
+pub mod milestones;
+pub mod review;
+pub mod team;
+pub mod recurring;
+pub mod allowlist;
+pub mod attestation;
+pub mod marketplace;
+pub mod offers;
 
 use state::*;
 use contexts::*;
 use events::*;
+use dispute::*;
+use slashing::*;
+use reputation::*;
+use bidding::*;
+use milestones::*;
+use review::*;
+use team::*;
+use recurring::*;
+use allowlist::*;
+use attestation::*;
+use marketplace::*;
+use offers::*;
 
 declare_id!("6gT2Yv1C1RdgN8ABQrbQ9dzzMbKVjLtRJ45ziSkN6nZc");
 
-const MAX_ACTIONS_PER_MINUTE: u32 = 10;
-const RATE_LIMIT_DURATION: i64 = 60;
+pub const MAX_ACTIONS_PER_MINUTE: u32 = 10;
+pub const RATE_LIMIT_DURATION: i64 = 60;
 
 #[program]
 pub mod solana_ai_nexus {
@@ -24,6 +51,15 @@ pub mod solana_ai_nexus {
         state.authority = ctx.accounts.authority.key();
         state.agent_count = 0;
         state.task_count = 0;
+        state.is_paused = false;
+        state.arbiter = ctx.accounts.authority.key();
+        state.dispute_window = DEFAULT_DISPUTE_WINDOW;
+        state.review_window = DEFAULT_REVIEW_WINDOW;
+        state.recurring_count = 0;
+        state.treasury = ctx.accounts.authority.key();
+        state.rate_limit_max_actions = MAX_ACTIONS_PER_MINUTE;
+        state.rate_limit_window = RATE_LIMIT_DURATION;
+        state.bump = *ctx.bumps.get("state").unwrap();
         Ok(())
     }
 
@@ -32,18 +68,40 @@ pub mod solana_ai_nexus {
         name: String,
         description: String,
         metadata_uri: String,
+        capabilities: u64,
     ) -> Result<()> {
+        Agent::validate_name(&name)?;
+        Agent::validate_description(&description)?;
+        Agent::validate_metadata_uri(&metadata_uri)?;
+        Agent::validate_capabilities(capabilities)?;
+
         let state = &mut ctx.accounts.state;
         let agent = &mut ctx.accounts.agent;
 
         agent.id = state.agent_count;
         agent.owner = ctx.accounts.owner.key();
+        agent.capabilities = capabilities;
         agent.name = name;
         agent.description = description;
         agent.metadata_uri = metadata_uri;
         agent.reputation_score = 0;
         agent.tasks_completed = 0;
         agent.is_active = true;
+        agent.operator = None;
+        agent.bump = *ctx.bumps.get("agent").unwrap();
+        agent.pending_owner = None;
+        agent.open_tasks = 0;
+        agent.bonded_stake = 0;
+        agent.tasks_expired = 0;
+        agent.disputes_lost = 0;
+        agent.tasks_cancelled = 0;
+        agent.total_earned = 0;
+        agent.rating_sum = 0;
+        agent.rating_count = 0;
+        agent.reputation_updated_at = Clock::get()?.unix_timestamp;
+        agent.attestation_key = None;
+        agent.is_listed = false;
+        agent.rented_until = 0;
 
         state.agent_count = state.agent_count.checked_add(1).unwrap();
         
@@ -55,49 +113,610 @@ pub mod solana_ai_nexus {
         description: String,
         reward: u64,
         deadline: i64,
+        required_capabilities: u64,
     ) -> Result<()> {
+        require!(
+            description.len() <= MAX_TASK_DESCRIPTION_LEN,
+            errors::CustomError::DescriptionTooLong
+        );
+        Agent::validate_capabilities(required_capabilities)?;
+        require!(reward > 0, errors::CustomError::InvalidReward);
+        require!(deadline > Clock::get()?.unix_timestamp, errors::CustomError::InvalidDeadline);
+
+        check_rate_limit(
+            &mut ctx.accounts.rate_limit,
+            ctx.accounts.creator.key(),
+            *ctx.bumps.get("rate_limit").unwrap(),
+            &ctx.accounts.state,
+        )?;
+
         let state = &mut ctx.accounts.state;
         let task = &mut ctx.accounts.task;
+        let escrow = &mut ctx.accounts.escrow;
+
+        task.initialize(
+            state.task_count,
+            ctx.accounts.creator.key(),
+            description,
+            reward,
+            deadline,
+            *ctx.bumps.get("task").unwrap(),
+        );
+        task.required_capabilities = required_capabilities;
+
+        escrow.task = task.key();
+        escrow.creator = task.creator;
+        escrow.amount = reward;
+        escrow.bump = *ctx.bumps.get("escrow").unwrap();
 
-        task.id = state.task_count;
-        task.creator = ctx.accounts.creator.key();
-        task.description = description;
-        task.reward = reward;
-        task.deadline = deadline;
-        task.status = TaskStatus::Pending;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.creator.to_account_info(),
+                    to: escrow.to_account_info(),
+                },
+            ),
+            reward,
+        )?;
 
         state.task_count = state.task_count.checked_add(1).unwrap();
         Ok(())
     }
 
-    pub fn assign_task(ctx: Context<AssignTask>, agent_id: u64) -> Result<()> {
+    /// `proof` is only needed when the task is private and the agent is
+    /// listed under the allowlist's Merkle root.
+    pub fn assign_task(ctx: Context<AssignTask>, _agent_id: u64, proof: Vec<[u8; 32]>) -> Result<()> {
+        check_rate_limit(
+            &mut ctx.accounts.rate_limit,
+            ctx.accounts.authority.key(),
+            *ctx.bumps.get("rate_limit").unwrap(),
+            &ctx.accounts.state,
+        )?;
+        check_allowlist(
+            &ctx.accounts.task,
+            &ctx.accounts.allowlist,
+            &ctx.accounts.agent,
+            &proof,
+        )?;
+
+        ctx.accounts.task.assign(&mut ctx.accounts.agent, &ctx.accounts.state)
+    }
+
+    /// Submits a result and commits to its content hash. With `attested` set, the
+    /// transaction must verify the agent attestation key's signature over
+    /// `attestation_message(task, result_hash)` in the preceding ed25519 instruction.
+    pub fn complete_task(
+        ctx: Context<CompleteTask>,
+        result_uri: String,
+        result_hash: [u8; 32],
+        hash_kind: HashKind,
+        attested: bool,
+    ) -> Result<()> {
+        require!(result_uri.len() <= MAX_RESULT_URI_LEN, errors::CustomError::ResultUriTooLong);
+
+        check_rate_limit(
+            &mut ctx.accounts.rate_limit,
+            ctx.accounts.authority.key(),
+            *ctx.bumps.get("rate_limit").unwrap(),
+            &ctx.accounts.state,
+        )?;
+
+        let task = &mut ctx.accounts.task;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        // A rejected result may be resubmitted until the deadline.
+        require!(
+            task.status == TaskStatus::InProgress || task.status == TaskStatus::Rejected,
+            errors::CustomError::InvalidTaskStatus
+        );
+        require!(!task.has_milestones(), errors::CustomError::TaskHasMilestones);
+        require!(!task.has_team(), errors::CustomError::TaskHasTeam);
+        require!(now <= task.deadline, errors::CustomError::TaskDeadlinePassed);
+        
+        task.status = TaskStatus::Submitted;
+        task.result_uri = Some(result_uri);
+        task.submitted_at = now;
+
+        commit_result(
+            task,
+            &ctx.accounts.agent,
+            result_hash,
+            hash_kind,
+            attested,
+            &ctx.accounts.instructions,
+        )
+    }
+
+    pub fn verify_result(ctx: Context<VerifyResult>, content_hash: [u8; 32]) -> Result<()> {
+        attestation::verify_result(ctx, content_hash)
+    }
+
+    pub fn approve_result<'info>(ctx: Context<'_, '_, '_, 'info, ApproveResult<'info>>) -> Result<()> {
+        review::approve_result(ctx)
+    }
+
+    pub fn reject_result(ctx: Context<RejectResult>, reason_uri: String) -> Result<()> {
+        review::reject_result(ctx, reason_uri)
+    }
+
+    pub fn finalize_task<'info>(ctx: Context<'_, '_, '_, 'info, FinalizeTask<'info>>) -> Result<()> {
+        review::finalize_task(ctx)
+    }
+
+    pub fn assign_team<'info>(
+        ctx: Context<'_, '_, '_, 'info, AssignTeam<'info>>,
+        members: Vec<TeamShare>,
+        lead_completes: bool,
+        proofs: Vec<Vec<[u8; 32]>>,
+    ) -> Result<()> {
+        team::assign_team(ctx, members, lead_completes, proofs)
+    }
+
+    pub fn submit_team_result(
+        ctx: Context<SubmitTeamResult>,
+        result_uri: String,
+        result_hash: [u8; 32],
+        hash_kind: HashKind,
+        attested: bool,
+    ) -> Result<()> {
+        team::submit_team_result(ctx, result_uri, result_hash, hash_kind, attested)
+    }
+
+    pub fn close_task_team(ctx: Context<CloseTaskTeam>) -> Result<()> {
+        team::close_task_team(ctx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_recurring_task(
+        ctx: Context<CreateRecurringTask>,
+        description: String,
+        reward: u64,
+        interval: i64,
+        run_duration: i64,
+        max_runs: u32,
+        first_run_at: i64,
+        required_capabilities: u64,
+    ) -> Result<()> {
+        recurring::create_recurring_task(
+            ctx,
+            description,
+            reward,
+            interval,
+            run_duration,
+            max_runs,
+            first_run_at,
+            required_capabilities,
+        )
+    }
+
+    pub fn spawn_recurring_task(ctx: Context<SpawnRecurringTask>) -> Result<()> {
+        recurring::spawn_recurring_task(ctx)
+    }
+
+    pub fn set_recurring_task_paused(ctx: Context<ManageRecurringTask>, paused: bool) -> Result<()> {
+        recurring::set_recurring_task_paused(ctx, paused)
+    }
+
+    pub fn cancel_recurring_task(ctx: Context<CancelRecurringTask>) -> Result<()> {
+        recurring::cancel_recurring_task(ctx)
+    }
+
+    pub fn submit_bid(
+        ctx: Context<SubmitBid>,
+        price: u64,
+        eta: i64,
+        proposal_uri: String,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        bidding::submit_bid(ctx, price, eta, proposal_uri, proof)
+    }
+
+    pub fn withdraw_bid(ctx: Context<WithdrawBid>) -> Result<()> {
+        bidding::withdraw_bid(ctx)
+    }
+
+    pub fn close_bid(ctx: Context<CloseBid>) -> Result<()> {
+        bidding::close_bid(ctx)
+    }
+
+    pub fn accept_bid<'info>(
+        ctx: Context<'_, '_, '_, 'info, AcceptBid<'info>>,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        bidding::accept_bid(ctx, proof)
+    }
+
+    pub fn initialize_marketplace(ctx: Context<InitializeMarketplace>) -> Result<()> {
+        marketplace::initialize_marketplace(ctx)
+    }
+
+    pub fn set_marketplace_fee(ctx: Context<SetMarketplaceFee>, fee_percentage: u16) -> Result<()> {
+        marketplace::set_marketplace_fee(ctx, fee_percentage)
+    }
+
+    pub fn withdraw_marketplace_fees(ctx: Context<WithdrawMarketplaceFees>, amount: u64) -> Result<()> {
+        marketplace::withdraw_marketplace_fees(ctx, amount)
+    }
+
+    pub fn create_listing(
+        ctx: Context<CreateListing>,
+        agent_id: u64,
+        price: u64,
+        description: String,
+    ) -> Result<()> {
+        marketplace::create_listing(ctx, agent_id, price, description)
+    }
+
+    pub fn purchase_listing(ctx: Context<PurchaseListing>) -> Result<()> {
+        marketplace::purchase_listing(ctx)
+    }
+
+    pub fn set_rental_fee(ctx: Context<SetMarketplaceFee>, rental_fee_percentage: u16) -> Result<()> {
+        marketplace::set_rental_fee(ctx, rental_fee_percentage)
+    }
+
+    pub fn create_rental_listing(
+        ctx: Context<CreateListing>,
+        agent_id: u64,
+        price_per_period: u64,
+        rental_period: i64,
+        max_rental_periods: u32,
+        description: String,
+    ) -> Result<()> {
+        marketplace::create_rental_listing(
+            ctx,
+            agent_id,
+            price_per_period,
+            rental_period,
+            max_rental_periods,
+            description,
+        )
+    }
+
+    pub fn rent_agent(ctx: Context<RentAgent>, periods: u32) -> Result<()> {
+        marketplace::rent_agent(ctx, periods)
+    }
+
+    pub fn expire_rental(ctx: Context<ExpireRental>) -> Result<()> {
+        marketplace::expire_rental(ctx)
+    }
+
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        marketplace::cancel_listing(ctx)
+    }
+
+    pub fn update_listing_price(ctx: Context<ManageListing>, price: u64) -> Result<()> {
+        marketplace::update_listing_price(ctx, price)
+    }
+
+    pub fn suspend_listing(ctx: Context<ModerateListing>) -> Result<()> {
+        marketplace::suspend_listing(ctx)
+    }
+
+    pub fn reinstate_listing(ctx: Context<ModerateListing>) -> Result<()> {
+        marketplace::reinstate_listing(ctx)
+    }
+
+    pub fn make_offer(ctx: Context<MakeOffer>, amount: u64) -> Result<()> {
+        offers::make_offer(ctx, amount)
+    }
+
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        offers::cancel_offer(ctx)
+    }
+
+    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+        offers::accept_offer(ctx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_auction_listing(
+        ctx: Context<CreateListing>,
+        agent_id: u64,
+        reserve_price: u64,
+        min_bid_increment: u64,
+        ends_at: i64,
+        extension_window: i64,
+        description: String,
+    ) -> Result<()> {
+        offers::create_auction_listing(
+            ctx,
+            agent_id,
+            reserve_price,
+            min_bid_increment,
+            ends_at,
+            extension_window,
+            description,
+        )
+    }
+
+    pub fn place_auction_bid(ctx: Context<PlaceAuctionBid>, amount: u64) -> Result<()> {
+        offers::place_auction_bid(ctx, amount)
+    }
+
+    pub fn refund_auction_bid(ctx: Context<RefundAuctionBid>) -> Result<()> {
+        offers::refund_auction_bid(ctx)
+    }
+
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        offers::settle_auction(ctx)
+    }
+
+    pub fn set_task_allowlist(
+        ctx: Context<SetTaskAllowlist>,
+        entries: Vec<AllowlistEntry>,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        allowlist::set_task_allowlist(ctx, entries, merkle_root)
+    }
+
+    pub fn remove_task_allowlist(ctx: Context<RemoveTaskAllowlist>) -> Result<()> {
+        allowlist::remove_task_allowlist(ctx)
+    }
+
+    pub fn add_milestones(ctx: Context<AddMilestones>, milestones: Vec<MilestoneInput>) -> Result<()> {
+        milestones::add_milestones(ctx, milestones)
+    }
+
+    pub fn submit_milestone(ctx: Context<SubmitMilestone>, index: u8, result_uri: String) -> Result<()> {
+        milestones::submit_milestone(ctx, index, result_uri)
+    }
+
+    pub fn approve_milestone(ctx: Context<ApproveMilestone>, index: u8) -> Result<()> {
+        milestones::approve_milestone(ctx, index)
+    }
+
+    pub fn finalize_milestone(ctx: Context<FinalizeMilestone>, index: u8) -> Result<()> {
+        milestones::finalize_milestone(ctx, index)
+    }
+
+    pub fn close_milestone_plan(ctx: Context<CloseMilestonePlan>) -> Result<()> {
+        milestones::close_milestone_plan(ctx)
+    }
+
+    pub fn set_task_delegate(ctx: Context<SetTaskDelegate>, delegate: Option<Pubkey>) -> Result<()> {
+        ctx.accounts.task.delegate = delegate;
+        Ok(())
+    }
+
+    pub fn set_agent_operator(ctx: Context<ManageAgent>, operator: Option<Pubkey>) -> Result<()> {
+        require!(ctx.accounts.agent.rented_until == 0, errors::CustomError::AgentRented);
+        ctx.accounts.agent.operator = operator;
+        Ok(())
+    }
+
+    pub fn set_attestation_key(ctx: Context<ManageAgent>, attestation_key: Option<Pubkey>) -> Result<()> {
+        ctx.accounts.agent.attestation_key = attestation_key;
+        Ok(())
+    }
+
+    pub fn update_agent(
+        ctx: Context<ManageAgent>,
+        name: Option<String>,
+        description: Option<String>,
+        metadata_uri: Option<String>,
+        capabilities: Option<u64>,
+    ) -> Result<()> {
+        let agent = &mut ctx.accounts.agent;
+
+        if let Some(name) = name {
+            Agent::validate_name(&name)?;
+            agent.name = name;
+        }
+        if let Some(description) = description {
+            Agent::validate_description(&description)?;
+            agent.description = description;
+        }
+        if let Some(metadata_uri) = metadata_uri {
+            Agent::validate_metadata_uri(&metadata_uri)?;
+            agent.metadata_uri = metadata_uri;
+        }
+        if let Some(capabilities) = capabilities {
+            Agent::validate_capabilities(capabilities)?;
+            agent.capabilities = capabilities;
+        }
+        Ok(())
+    }
+
+    pub fn set_agent_active(ctx: Context<ManageAgent>, is_active: bool) -> Result<()> {
+        require!(
+            is_active || !ctx.accounts.agent.is_listed,
+            errors::CustomError::AgentListed
+        );
+        ctx.accounts.agent.is_active = is_active;
+        Ok(())
+    }
+
+    pub fn transfer_agent_ownership(ctx: Context<ManageAgent>, new_owner: Option<Pubkey>) -> Result<()> {
+        require!(!ctx.accounts.agent.is_listed, errors::CustomError::AgentListed);
+        ctx.accounts.agent.pending_owner = new_owner;
+        Ok(())
+    }
+
+    pub fn accept_agent_ownership(ctx: Context<AcceptAgentOwnership>) -> Result<()> {
+        let agent = &mut ctx.accounts.agent;
+
+        require!(!agent.is_listed, errors::CustomError::AgentListed);
+
+        // The previous owner's operator and attestation keys do not carry over.
+        transfer_agent(agent, ctx.accounts.new_owner.key(), Clock::get()?.unix_timestamp);
+
+        Ok(())
+    }
+
+    pub fn close_agent(ctx: Context<CloseAgent>) -> Result<()> {
+        require!(ctx.accounts.agent.open_tasks == 0, errors::CustomError::AgentHasOpenTasks);
+        require!(!ctx.accounts.agent.is_listed, errors::CustomError::AgentListed);
+        // The stake vault is owned by the agent PDA, so it must be emptied first.
+        require!(ctx.accounts.agent.bonded_stake == 0, errors::CustomError::StakeStillBonded);
+        Ok(())
+    }
+
+    pub fn cancel_task(ctx: Context<CancelTask>) -> Result<()> {
         let task = &mut ctx.accounts.task;
-        let agent = &ctx.accounts.agent;
 
-        require!(agent.is_active, errors::CustomError::AgentNotActive);
         require!(task.status == TaskStatus::Pending, errors::CustomError::InvalidTaskStatus);
 
-        task.agent_id = Some(agent_id);
-        task.status = TaskStatus::InProgress;
+        // Closing the escrow returns the locked reward and its rent to the creator.
+        task.status = TaskStatus::Cancelled;
+        ctx.accounts.escrow.amount = 0;
+
         Ok(())
     }
 
-    pub fn complete_task(ctx: Context<CompleteTask>, result_uri: String) -> Result<()> {
+    /// Lets the assigned agent give a task back before it is settled. The
+    /// creator is refunded and the cancellation counts against the agent; team
+    /// tasks pass the team and its other member agents as remaining accounts.
+    pub fn cancel_assigned_task<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelAssignedTask<'info>>,
+    ) -> Result<()> {
         let task = &mut ctx.accounts.task;
         let agent = &mut ctx.accounts.agent;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            task.status == TaskStatus::InProgress
+                || task.status == TaskStatus::PartiallyCompleted
+                || task.status == TaskStatus::Rejected,
+            errors::CustomError::InvalidTaskStatus
+        );
+
+        // Closing the escrow refunds the creator whatever has not been paid out.
+        task.status = TaskStatus::Cancelled;
+        ctx.accounts.escrow.amount = 0;
+
+        agent.open_tasks = agent.open_tasks.saturating_sub(1);
+        if task.has_team() {
+            release_team_members(task.key(), ctx.remaining_accounts)?;
+        }
+
+        apply_outcome(agent, ReputationOutcome::Cancelled, now)?;
+
+        emit!(TaskCancelledEvent {
+            task_id: task.id,
+            agent_id: agent.id,
+            timestamp: now,
+        });
 
-        require!(task.status == TaskStatus::InProgress, errors::CustomError::InvalidTaskStatus);
-        
-        task.status = TaskStatus::Completed;
-        task.result_uri = Some(result_uri);
-        agent.tasks_completed = agent.tasks_completed.checked_add(1).unwrap();
-        
         Ok(())
     }
 
+    /// Team tasks pass the team and its other member agents as remaining
+    /// accounts so every member is released; only the lead is slashed.
+    /// Milestone tasks pass their plan and the agent owner: milestones
+    /// submitted on time are paid, and only the undelivered share is slashed.
+    pub fn expire_task<'info>(ctx: Context<'_, '_, '_, 'info, ExpireTask<'info>>) -> Result<()> {
+        let task = &mut ctx.accounts.task;
+        let agent = &mut ctx.accounts.agent;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            task.status == TaskStatus::InProgress
+                || task.status == TaskStatus::PartiallyCompleted
+                || task.status == TaskStatus::Rejected,
+            errors::CustomError::InvalidTaskStatus
+        );
+        require!(now > task.deadline, errors::CustomError::TaskDeadlineNotReached);
+
+        // A rejected agent that missed the deadline keeps the dispute window to contest it.
+        if task.status == TaskStatus::Rejected {
+            require!(
+                now > task.rejected_at.checked_add(ctx.accounts.state.dispute_window).unwrap(),
+                errors::CustomError::DisputeWindowOpen
+            );
+        }
+
+        let mut slash_scale = BPS_DENOMINATOR;
+        if task.has_milestones() {
+            let plan = ctx.accounts.plan.as_mut().ok_or(errors::CustomError::InvalidMilestones)?;
+            let agent_owner = ctx.accounts.agent_owner.as_ref().ok_or(errors::CustomError::Unauthorized)?;
+            pay_submitted_milestones(
+                agent,
+                plan,
+                &mut ctx.accounts.escrow,
+                &agent_owner.to_account_info(),
+                task.id,
+                now,
+            )?;
+
+            // Every outstanding milestone was delivered on time; nothing expired.
+            if plan.is_complete() {
+                return complete_milestone_task(task, agent, now);
+            }
+            slash_scale = (ctx.accounts.escrow.amount as u128)
+                .checked_mul(BPS_DENOMINATOR as u128)
+                .unwrap()
+                .checked_div(task.reward as u128)
+                .unwrap() as u64;
+        }
+
+        // Closing the escrow refunds the creator whatever has not been paid out.
+        task.status = TaskStatus::Expired;
+        ctx.accounts.escrow.amount = 0;
+
+        agent.open_tasks = agent.open_tasks.saturating_sub(1);
+        if task.has_team() {
+            expire_team_members(task.key(), ctx.remaining_accounts, now)?;
+        }
+
+        slash_agent_stake(
+            &ctx.accounts.state,
+            agent,
+            task,
+            slash_scale,
+            SlashAccounts {
+                stake_account: &ctx.accounts.stake_account.to_account_info(),
+                creator_token_account: ctx.accounts.creator_token_account.as_ref(),
+                treasury_token_account: ctx.accounts.treasury_token_account.as_ref(),
+                token_program: &ctx.accounts.token_program.to_account_info(),
+            },
+            SlashReason::MissedDeadline,
+        )?;
+
+        apply_outcome(agent, ReputationOutcome::Expired, now)?;
+
+        emit!(TaskExpiredEvent {
+            task_id: task.id,
+            agent_id: agent.id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_dispute_config(
+        ctx: Context<SetDisputeConfig>,
+        arbiter: Pubkey,
+        dispute_window: i64,
+    ) -> Result<()> {
+        dispute::set_dispute_config(ctx, arbiter, dispute_window)
+    }
+
+    pub fn open_dispute(ctx: Context<OpenDispute>, reason_uri: String) -> Result<()> {
+        dispute::open_dispute(ctx, reason_uri)
+    }
+
+    pub fn submit_evidence(ctx: Context<SubmitEvidence>, uri: String, hash: [u8; 32]) -> Result<()> {
+        dispute::submit_evidence(ctx, uri, hash)
+    }
+
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveDispute<'info>>,
+        creator_share_bps: u16,
+    ) -> Result<()> {
+        dispute::resolve_dispute(ctx, creator_share_bps)
+    }
+
+    pub fn close_dispute(ctx: Context<CloseDispute>) -> Result<()> {
+        dispute::close_dispute(ctx)
+    }
+
     pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
         let staker_key = ctx.accounts.staker.key();
         
+        // Bond the tokens to the agent; its PDA owns the stake account.
         token::transfer(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
@@ -110,8 +729,49 @@ pub mod solana_ai_nexus {
             amount,
         )?;
 
+        let agent = &mut ctx.accounts.agent;
+        agent.bonded_stake = agent.bonded_stake.checked_add(amount).unwrap();
+
         emit!(StakeEvent {
             staker: staker_key,
+            agent_id: agent.id,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn unstake_tokens(ctx: Context<UnstakeTokens>, amount: u64) -> Result<()> {
+        let agent = &ctx.accounts.agent;
+
+        require!(agent.open_tasks == 0, errors::CustomError::AgentHasOpenTasks);
+        // Bonded stake is sold along with a listed agent.
+        require!(!agent.is_listed, errors::CustomError::AgentListed);
+        require!(amount <= agent.bonded_stake, errors::CustomError::InsufficientStake);
+
+        let id_bytes = agent.id.to_le_bytes();
+        let seeds: &[&[u8]] = &[Agent::SEED, &id_bytes, &[agent.bump]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.stake_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: agent.to_account_info(),
+                },
+                &[seeds],
+            ),
+            amount,
+        )?;
+
+        let agent = &mut ctx.accounts.agent;
+        agent.bonded_stake = agent.bonded_stake.checked_sub(amount).unwrap();
+
+        emit!(UnstakeEvent {
+            staker: ctx.accounts.staker.key(),
+            agent_id: agent.id,
             amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
@@ -119,10 +779,37 @@ pub mod solana_ai_nexus {
         Ok(())
     }
 
+    pub fn set_stake_config(
+        ctx: Context<AdminOnly>,
+        stake_mint: Pubkey,
+        min_agent_stake: u64,
+        stake_reward_bps: u16,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.authority,
+            errors::CustomError::Unauthorized
+        );
+        let state = &mut ctx.accounts.state;
+        state.stake_mint = stake_mint;
+        state.min_agent_stake = min_agent_stake;
+        state.stake_reward_bps = stake_reward_bps;
+        Ok(())
+    }
+
+    pub fn rate_task(ctx: Context<RateTask>, rating: u8) -> Result<()> {
+        reputation::rate_task(ctx, rating)
+    }
+
+    pub fn decay_reputation(ctx: Context<DecayReputation>) -> Result<()> {
+        reputation::decay_reputation(ctx)
+    }
+
+    /// Emergency override of a derived score; every use is logged with its reason.
     pub fn update_reputation(
         ctx: Context<UpdateReputation>,
         agent_id: u64,
         score_delta: i32,
+        reason: String,
     ) -> Result<()> {
         let agent = &mut ctx.accounts.agent;
         
@@ -130,21 +817,33 @@ pub mod solana_ai_nexus {
             ctx.accounts.authority.key() == ctx.accounts.state.authority,
             errors::CustomError::Unauthorized
         );
+        require!(
+            !reason.is_empty() && reason.len() <= MAX_OVERRIDE_REASON_LEN,
+            errors::CustomError::InvalidOverrideReason
+        );
+
+        // Decay to now first, as every outcome does, so the delta is applied
+        // to the current score and pending decay is not discarded.
+        let old_score = agent.reputation_score;
+        let now = Clock::get()?.unix_timestamp;
+        let decayed = decayed_score(old_score, now - agent.reputation_updated_at);
 
         let new_score = if score_delta >= 0 {
-            agent.reputation_score.checked_add(score_delta as u32)
+            decayed.checked_add(score_delta as u32)
         } else {
-            agent.reputation_score.checked_sub(score_delta.abs() as u32)
+            decayed.checked_sub(score_delta.unsigned_abs())
         }.ok_or(errors::CustomError::InvalidReputationScore)?;
 
-        let old_score = agent.reputation_score;
         agent.reputation_score = new_score;
+        agent.reputation_updated_at = now;
 
-        emit!(ReputationUpdateEvent {
+        emit!(ReputationOverrideEvent {
             agent_id,
+            authority: ctx.accounts.authority.key(),
             old_score,
             new_score,
-            timestamp: Clock::get()?.unix_timestamp,
+            reason,
+            timestamp: now,
         });
 
         Ok(())
@@ -155,6 +854,84 @@ pub mod solana_ai_nexus {
             ctx.accounts.authority.key() == ctx.accounts.state.authority,
             errors::CustomError::Unauthorized
         );
+
+        ctx.accounts.state.is_paused = true;
+
+        emit!(SystemEvent {
+            event_type: "protocol_paused".to_string(),
+            severity: EventSeverity::Critical,
+            timestamp: Clock::get()?.unix_timestamp,
+            details: format!("Paused by {}", ctx.accounts.authority.key()),
+        });
+
+        Ok(())
+    }
+
+    pub fn unpause_protocol(ctx: Context<AdminOnly>) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.authority,
+            errors::CustomError::Unauthorized
+        );
+
+        ctx.accounts.state.is_paused = false;
+
+        emit!(SystemEvent {
+            event_type: "protocol_unpaused".to_string(),
+            severity: EventSeverity::Info,
+            timestamp: Clock::get()?.unix_timestamp,
+            details: format!("Unpaused by {}", ctx.accounts.authority.key()),
+        });
+
+        Ok(())
+    }
+
+    pub fn set_rate_limit(
+        ctx: Context<AdminOnly>,
+        max_actions: u32,
+        window: i64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.authority,
+            errors::CustomError::Unauthorized
+        );
+        require!(max_actions > 0 && window > 0, errors::CustomError::InvalidRateLimit);
+
+        let state = &mut ctx.accounts.state;
+        state.rate_limit_max_actions = max_actions;
+        state.rate_limit_window = window;
+        Ok(())
+    }
+
+    pub fn set_review_window(ctx: Context<AdminOnly>, review_window: i64) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.authority,
+            errors::CustomError::Unauthorized
+        );
+        require!(review_window >= 0, errors::CustomError::InvalidReviewWindow);
+
+        ctx.accounts.state.review_window = review_window;
+        Ok(())
+    }
+
+    pub fn set_slash_config(
+        ctx: Context<AdminOnly>,
+        slash_bps: u16,
+        slash_treasury_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.state.authority,
+            errors::CustomError::Unauthorized
+        );
+        require!(
+            slash_bps as u64 <= BPS_DENOMINATOR && slash_treasury_bps as u64 <= BPS_DENOMINATOR,
+            errors::CustomError::InvalidShare
+        );
+
+        let state = &mut ctx.accounts.state;
+        state.slash_bps = slash_bps;
+        state.slash_treasury_bps = slash_treasury_bps;
+        state.treasury = treasury;
         Ok(())
     }
 
@@ -171,6 +948,38 @@ pub mod solana_ai_nexus {
     }
 }
 
+/// Counts an action against the signer's sliding-window rate limit.
+pub fn check_rate_limit(
+    rate_limit: &mut Account<RateLimit>,
+    signer: Pubkey,
+    bump: u8,
+    state: &State,
+) -> Result<()> {
+    rate_limit.signer = signer;
+    rate_limit.bump = bump;
+    rate_limit.record_action(
+        Clock::get()?.unix_timestamp,
+        state.rate_limit_window,
+        state.rate_limit_max_actions,
+    )
+}
+
+/// Moves lamports out of an account owned by this program without a system CPI.
+pub fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let from_balance = from
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(errors::CustomError::InsufficientEscrow)?;
+    let to_balance = to
+        .lamports()
+        .checked_add(amount)
+        .ok_or(errors::CustomError::InsufficientEscrow)?;
+
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **to.try_borrow_mut_lamports()? = to_balance;
+    Ok(())
+}
+
 #[error_code]
 pub enum CustomError {
     #[msg("Agent is not active")]
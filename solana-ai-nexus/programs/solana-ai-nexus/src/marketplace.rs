@@ -1,13 +1,23 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount};
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use crate::errors::CustomError;
+use crate::events::AgentOwnershipTransferredEvent;
+use crate::state::{Agent, State, BPS_DENOMINATOR};
+
+pub const DEFAULT_MARKETPLACE_FEE_BPS: u16 = 200;
+pub const MAX_LISTING_DESCRIPTION_LEN: usize = 200;
 
 #[account]
 pub struct Marketplace {
     pub authority: Pubkey,
     pub listing_count: u64,
     pub total_volume: u64,
-    pub fee_percentage: u8,
+    pub fee_percentage: u16, // In basis points
     pub is_active: bool,
+    // Token sales are priced in and fees are collected into the treasury
+    pub payment_mint: Pubkey,
+    pub bump: u8,
+    pub rental_fee_percentage: u16, // In basis points
 }
 
 #[account]
@@ -21,31 +31,143 @@ pub struct AgentListing {
     pub status: ListingStatus,
     pub rating: u8,
     pub reviews_count: u32,
+    pub listing_type: ListingType,
+    // Rental listings only: `price` is charged per period of this many seconds
+    pub rental_period: i64,
+    pub max_rental_periods: u32,
+    // Auction listings only: `price` is the reserve
+    pub min_bid_increment: u64,
+    pub ends_at: i64,
+    // Bids placed this close to `ends_at` push it back to `extension_window` from the bid
+    pub extension_window: i64,
+    pub highest_bid: u64,
+    pub highest_bidder: Option<Pubkey>,
+    // Auction bids whose escrow has not been refunded or settled yet
+    pub open_bids: u32,
+}
+
+/// Grants the renter the agent's operator key until `expires_at`. The owner's
+/// operator is restored when the rental is expired.
+#[account]
+pub struct AgentRental {
+    pub listing: Pubkey,
+    pub agent_id: u64,
+    pub owner: Pubkey,
+    pub renter: Pubkey,
+    pub previous_operator: Option<Pubkey>,
+    pub periods: u32,
+    pub price: u64,
+    pub started_at: i64,
+    pub expires_at: i64,
+    pub bump: u8,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
 pub enum ListingStatus {
     Active,
     Sold,
     Cancelled,
     Suspended,
+    Rented,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum ListingType {
+    Sale,
+    Rental,
+    Auction,
 }
 
 #[derive(Accounts)]
 pub struct InitializeMarketplace<'info> {
-    #[account(init, payer = authority, space = 8 + Marketplace::SPACE)]
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        has_one = authority @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Marketplace::SPACE,
+        seeds = [Marketplace::SEED],
+        bump
+    )]
     pub marketplace: Account<'info, Marketplace>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [Marketplace::TREASURY_SEED],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub treasury: Account<'info, TokenAccount>,
     #[account(mut)]
     pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct CreateListing<'info> {
+pub struct SetMarketplaceFee<'info> {
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        has_one = authority @ CustomError::Unauthorized
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawMarketplaceFees<'info> {
+    #[account(
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        has_one = authority @ CustomError::Unauthorized
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut, seeds = [Marketplace::TREASURY_SEED], bump)]
+    pub treasury: Account<'info, TokenAccount>,
     #[account(mut)]
+    pub destination: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(agent_id: u64)]
+pub struct CreateListing<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
     pub marketplace: Account<'info, Marketplace>,
-    #[account(init, payer = seller, space = 8 + AgentListing::SPACE)]
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + AgentListing::SPACE,
+        seeds = [AgentListing::SEED, &marketplace.listing_count.to_le_bytes()],
+        bump
+    )]
     pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == agent_id @ CustomError::AgentIdMismatch,
+        constraint = agent.owner == seller.key() @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
     #[account(mut)]
     pub seller: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -53,40 +175,224 @@ pub struct CreateListing<'info> {
 
 #[derive(Accounts)]
 pub struct PurchaseListing<'info> {
-    #[account(mut)]
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
     pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = listing.listing_type == ListingType::Sale @ CustomError::InvalidListingType
+    )]
     pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch,
+        constraint = agent.owner == listing.seller @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
     #[account(mut)]
     pub buyer: Signer<'info>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
     pub buyer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == listing.seller @ CustomError::Unauthorized,
+        constraint = seller_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
     pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [Marketplace::TREASURY_SEED], bump)]
+    pub treasury: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RentAgent<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        constraint = listing.listing_type == ListingType::Rental @ CustomError::InvalidListingType
+    )]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch,
+        constraint = agent.owner == listing.seller @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        init,
+        payer = renter,
+        space = 8 + AgentRental::SPACE,
+        seeds = [AgentRental::SEED, listing.key().as_ref()],
+        bump
+    )]
+    pub rental: Account<'info, AgentRental>,
+    #[account(mut)]
+    pub renter: Signer<'info>,
+    #[account(
+        mut,
+        constraint = renter_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub renter_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == listing.seller @ CustomError::Unauthorized,
+        constraint = owner_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [Marketplace::TREASURY_SEED], bump)]
+    pub treasury: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireRental<'info> {
+    #[account(mut)]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [AgentRental::SEED, listing.key().as_ref()],
+        bump = rental.bump,
+        close = renter
+    )]
+    pub rental: Account<'info, AgentRental>,
+    /// CHECK: receives the rental account's rent; address is pinned to the renter.
+    #[account(mut, address = rental.renter @ CustomError::Unauthorized)]
+    pub renter: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(mut, has_one = seller @ CustomError::Unauthorized, close = seller)]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageListing<'info> {
+    #[account(mut, has_one = seller @ CustomError::Unauthorized)]
+    pub listing: Account<'info, AgentListing>,
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModerateListing<'info> {
+    #[account(
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        has_one = authority @ CustomError::Unauthorized
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub listing: Account<'info, AgentListing>,
+    pub authority: Signer<'info>,
 }
 
 impl Marketplace {
+    pub const SEED: &'static [u8] = b"marketplace";
+    pub const TREASURY_SEED: &'static [u8] = b"marketplace_treasury";
     pub const SPACE: usize = 8 + // discriminator
                             32 + // authority
                             8 + // listing_count
                             8 + // total_volume
-                            1 + // fee_percentage
+                            2 + // fee_percentage
                             1 + // is_active
+                            32 + // payment_mint
+                            1 + // bump
+                            2 + // rental_fee_percentage
                             64; // padding
+
+    pub fn fee_for(&self, price: u64) -> u64 {
+        bps_of(price, self.fee_percentage)
+    }
+
+    /// Protocol share of a rental payment; the rest goes to the agent owner.
+    pub fn rental_fee_for(&self, price: u64) -> u64 {
+        bps_of(price, self.rental_fee_percentage)
+    }
+}
+
+fn bps_of(amount: u64, bps: u16) -> u64 {
+    (amount as u128)
+        .checked_mul(bps as u128)
+        .unwrap()
+        .checked_div(BPS_DENOMINATOR as u128)
+        .unwrap() as u64
 }
 
 impl AgentListing {
+    pub const SEED: &'static [u8] = b"listing";
     pub const SPACE: usize = 8 + // discriminator
                             8 + // listing_id
                             8 + // agent_id
                             32 + // seller
                             8 + // price
-                            200 + // description
+                            4 + MAX_LISTING_DESCRIPTION_LEN + // description
                             8 + // created_at
                             1 + // status
                             1 + // rating
                             4 + // reviews_count
+                            1 + // listing_type
+                            8 + // rental_period
+                            4 + // max_rental_periods
+                            8 + // min_bid_increment
+                            8 + // ends_at
+                            8 + // extension_window
+                            8 + // highest_bid
+                            33 + // highest_bidder
+                            4 + // open_bids
+                            64; // padding
+}
+
+impl AgentRental {
+    pub const SEED: &'static [u8] = b"rental";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // listing
+                            8 + // agent_id
+                            32 + // owner
+                            32 + // renter
+                            33 + // previous_operator
+                            4 + // periods
+                            8 + // price
+                            8 + // started_at
+                            8 + // expires_at
+                            1 + // bump
                             64; // padding
 }
 
@@ -96,6 +402,7 @@ pub struct ListingCreated {
     pub agent_id: u64,
     pub seller: Pubkey,
     pub price: u64,
+    pub listing_type: ListingType,
     pub timestamp: i64,
 }
 
@@ -105,39 +412,203 @@ pub struct ListingSold {
     pub buyer: Pubkey,
     pub seller: Pubkey,
     pub price: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub listing_id: u64,
+    pub agent_id: u64,
+    pub seller: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ListingPriceUpdated {
+    pub listing_id: u64,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ListingSuspended {
+    pub listing_id: u64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ListingReinstated {
+    pub listing_id: u64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AgentRented {
+    pub listing_id: u64,
+    pub agent_id: u64,
+    pub renter: Pubkey,
+    pub owner: Pubkey,
+    pub periods: u32,
+    pub price: u64,
+    pub fee: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RentalExpired {
+    pub listing_id: u64,
+    pub agent_id: u64,
+    pub renter: Pubkey,
     pub timestamp: i64,
 }
 
-// Add these instructions to your solana_ai_nexus module in lib.rs
 pub fn initialize_marketplace(ctx: Context<InitializeMarketplace>) -> Result<()> {
     let marketplace = &mut ctx.accounts.marketplace;
     marketplace.authority = ctx.accounts.authority.key();
     marketplace.listing_count = 0;
     marketplace.total_volume = 0;
-    marketplace.fee_percentage = 2; // 2% fee
+    marketplace.fee_percentage = DEFAULT_MARKETPLACE_FEE_BPS;
     marketplace.is_active = true;
+    marketplace.payment_mint = ctx.accounts.payment_mint.key();
+    marketplace.bump = *ctx.bumps.get("marketplace").unwrap();
+    marketplace.rental_fee_percentage = DEFAULT_MARKETPLACE_FEE_BPS;
     Ok(())
 }
 
-pub fn create_listing(
-    ctx: Context<CreateListing>,
+pub fn set_marketplace_fee(ctx: Context<SetMarketplaceFee>, fee_percentage: u16) -> Result<()> {
+    require!(fee_percentage as u64 <= BPS_DENOMINATOR, CustomError::InvalidFee);
+
+    ctx.accounts.marketplace.fee_percentage = fee_percentage;
+    Ok(())
+}
+
+pub fn set_rental_fee(ctx: Context<SetMarketplaceFee>, rental_fee_percentage: u16) -> Result<()> {
+    require!(rental_fee_percentage as u64 <= BPS_DENOMINATOR, CustomError::InvalidFee);
+
+    ctx.accounts.marketplace.rental_fee_percentage = rental_fee_percentage;
+    Ok(())
+}
+
+/// Moves collected fees out of the treasury, signed by the marketplace PDA.
+pub fn withdraw_marketplace_fees(ctx: Context<WithdrawMarketplaceFees>, amount: u64) -> Result<()> {
+    let seeds: &[&[u8]] = &[Marketplace::SEED, &[ctx.accounts.marketplace.bump]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.treasury.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.marketplace.to_account_info(),
+            },
+            &[seeds],
+        ),
+        amount,
+    )
+}
+
+/// Transfers `amount` from `from`, sending `fee` of it to the treasury and the
+/// rest to `payee`. Escrow accounts owned by the marketplace PDA pass its
+/// seeds as `signer`.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_with_fee<'info>(
+    token_program: &Program<'info, Token>,
+    from: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    payee: &Account<'info, TokenAccount>,
+    treasury: &Account<'info, TokenAccount>,
+    amount: u64,
+    fee: u64,
+) -> Result<()> {
+    let payee_amount = amount.checked_sub(fee).unwrap();
+    for (destination, amount) in [
+        (payee.to_account_info(), payee_amount),
+        (treasury.to_account_info(), fee),
+    ] {
+        if amount > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    token::Transfer {
+                        from: from.clone(),
+                        to: destination,
+                        authority: authority.clone(),
+                    },
+                    signer,
+                ),
+                amount,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Hands an agent to `new_owner`, whether sold or transferred directly. Keys
+/// the previous owner configured do not carry over.
+pub fn transfer_agent(agent: &mut Agent, new_owner: Pubkey, now: i64) {
+    let old_owner = agent.owner;
+    agent.owner = new_owner;
+    agent.pending_owner = None;
+    agent.operator = None;
+    agent.attestation_key = None;
+    agent.is_listed = false;
+
+    emit!(AgentOwnershipTransferredEvent {
+        agent_id: agent.id,
+        old_owner,
+        new_owner,
+        timestamp: now,
+    });
+}
+
+/// Opens a listing for the seller's agent and locks the agent until the
+/// listing is sold or closed.
+pub fn open_listing(
+    accounts: &mut CreateListing,
     agent_id: u64,
     price: u64,
     description: String,
+    listing_type: ListingType,
 ) -> Result<()> {
-    let marketplace = &mut ctx.accounts.marketplace;
-    let listing = &mut ctx.accounts.listing;
+    require!(price > 0, CustomError::InvalidPrice);
+    require!(
+        description.len() <= MAX_LISTING_DESCRIPTION_LEN,
+        CustomError::DescriptionTooLong
+    );
+
+    let agent = &mut accounts.agent;
+    require!(!agent.is_listed, CustomError::AgentListed);
+    require!(agent.open_tasks == 0, CustomError::AgentHasOpenTasks);
+    agent.is_listed = true;
+
+    let marketplace = &mut accounts.marketplace;
+    let listing = &mut accounts.listing;
     let clock = Clock::get()?;
 
     listing.listing_id = marketplace.listing_count;
     listing.agent_id = agent_id;
-    listing.seller = ctx.accounts.seller.key();
+    listing.seller = accounts.seller.key();
     listing.price = price;
     listing.description = description;
     listing.created_at = clock.unix_timestamp;
     listing.status = ListingStatus::Active;
     listing.rating = 0;
     listing.reviews_count = 0;
+    listing.listing_type = listing_type;
+    listing.rental_period = 0;
+    listing.max_rental_periods = 0;
+    listing.min_bid_increment = 0;
+    listing.ends_at = 0;
+    listing.extension_window = 0;
+    listing.highest_bid = 0;
+    listing.highest_bidder = None;
+    listing.open_bids = 0;
 
     marketplace.listing_count = marketplace.listing_count.checked_add(1).unwrap();
 
@@ -146,50 +617,247 @@ pub fn create_listing(
         agent_id: listing.agent_id,
         seller: listing.seller,
         price: listing.price,
+        listing_type,
         timestamp: clock.unix_timestamp,
     });
 
     Ok(())
 }
 
+pub fn create_listing(
+    ctx: Context<CreateListing>,
+    agent_id: u64,
+    price: u64,
+    description: String,
+) -> Result<()> {
+    open_listing(ctx.accounts, agent_id, price, description, ListingType::Sale)
+}
+
+/// Lists the agent for rent at `price_per_period` for each `rental_period`
+/// seconds, up to `max_rental_periods` periods per rental.
+pub fn create_rental_listing(
+    ctx: Context<CreateListing>,
+    agent_id: u64,
+    price_per_period: u64,
+    rental_period: i64,
+    max_rental_periods: u32,
+    description: String,
+) -> Result<()> {
+    require!(
+        rental_period > 0 && max_rental_periods > 0,
+        CustomError::InvalidRentalTerms
+    );
+
+    open_listing(ctx.accounts, agent_id, price_per_period, description, ListingType::Rental)?;
+
+    let listing = &mut ctx.accounts.listing;
+    listing.rental_period = rental_period;
+    listing.max_rental_periods = max_rental_periods;
+    Ok(())
+}
+
 pub fn purchase_listing(ctx: Context<PurchaseListing>) -> Result<()> {
     let marketplace = &mut ctx.accounts.marketplace;
     let listing = &mut ctx.accounts.listing;
     let clock = Clock::get()?;
 
     require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(ctx.accounts.agent.open_tasks == 0, CustomError::AgentHasOpenTasks);
 
-    // Calculate fees
-    let fee_amount = (listing.price as u128)
-        .checked_mul(marketplace.fee_percentage as u128)
-        .unwrap()
-        .checked_div(100)
-        .unwrap() as u64;
-    let seller_amount = listing.price.checked_sub(fee_amount).unwrap();
-
-    // Transfer tokens from buyer to seller
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
-                from: ctx.accounts.buyer_token_account.to_account_info(),
-                to: ctx.accounts.seller_token_account.to_account_info(),
-                authority: ctx.accounts.buyer.to_account_info(),
-            },
-        ),
-        seller_amount,
+    // Transfer tokens from buyer to seller, and the fee to the treasury
+    let fee_amount = marketplace.fee_for(listing.price);
+    pay_with_fee(
+        &ctx.accounts.token_program,
+        ctx.accounts.buyer_token_account.to_account_info(),
+        ctx.accounts.buyer.to_account_info(),
+        &[],
+        &ctx.accounts.seller_token_account,
+        &ctx.accounts.treasury,
+        listing.price,
+        fee_amount,
     )?;
 
     listing.status = ListingStatus::Sold;
     marketplace.total_volume = marketplace.total_volume.checked_add(listing.price).unwrap();
 
+    // The agent changes hands with the payment.
+    transfer_agent(&mut ctx.accounts.agent, ctx.accounts.buyer.key(), clock.unix_timestamp);
+
     emit!(ListingSold {
         listing_id: listing.listing_id,
         buyer: ctx.accounts.buyer.key(),
         seller: listing.seller,
         price: listing.price,
+        fee: fee_amount,
         timestamp: clock.unix_timestamp,
     });
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Rents the agent for `periods` rental periods. The renter becomes the
+/// agent's operator until the rental expires; payment is split between the
+/// owner and the treasury by the rental fee.
+pub fn rent_agent(ctx: Context<RentAgent>, periods: u32) -> Result<()> {
+    let marketplace = &mut ctx.accounts.marketplace;
+    let listing = &mut ctx.accounts.listing;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(periods > 0, CustomError::InvalidRentalTerms);
+    require!(periods <= listing.max_rental_periods, CustomError::RentalTooLong);
+    // Work the owner took on stays the owner's; the renter starts with a clean slate.
+    require!(ctx.accounts.agent.open_tasks == 0, CustomError::AgentHasOpenTasks);
+
+    let price = listing.price.checked_mul(periods as u64).unwrap();
+    let fee_amount = marketplace.rental_fee_for(price);
+    pay_with_fee(
+        &ctx.accounts.token_program,
+        ctx.accounts.renter_token_account.to_account_info(),
+        ctx.accounts.renter.to_account_info(),
+        &[],
+        &ctx.accounts.owner_token_account,
+        &ctx.accounts.treasury,
+        price,
+        fee_amount,
+    )?;
+
+    let expires_at = now
+        .checked_add(listing.rental_period.checked_mul(periods as i64).unwrap())
+        .unwrap();
+
+    let agent = &mut ctx.accounts.agent;
+    let rental = &mut ctx.accounts.rental;
+    rental.listing = listing.key();
+    rental.agent_id = agent.id;
+    rental.owner = agent.owner;
+    rental.renter = ctx.accounts.renter.key();
+    rental.previous_operator = agent.operator;
+    rental.periods = periods;
+    rental.price = price;
+    rental.started_at = now;
+    rental.expires_at = expires_at;
+    rental.bump = *ctx.bumps.get("rental").unwrap();
+
+    agent.operator = Some(rental.renter);
+    agent.rented_until = expires_at;
+    listing.status = ListingStatus::Rented;
+    marketplace.total_volume = marketplace.total_volume.checked_add(price).unwrap();
+
+    emit!(AgentRented {
+        listing_id: listing.listing_id,
+        agent_id: agent.id,
+        renter: rental.renter,
+        owner: rental.owner,
+        periods,
+        price,
+        fee: fee_amount,
+        expires_at,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Permissionless once the rental has run out: hands the operator key back to
+/// the owner's previous operator and reopens the listing.
+pub fn expire_rental(ctx: Context<ExpireRental>) -> Result<()> {
+    let rental = &ctx.accounts.rental;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(now >= rental.expires_at, CustomError::RentalNotExpired);
+
+    let agent = &mut ctx.accounts.agent;
+    agent.operator = rental.previous_operator;
+    agent.rented_until = 0;
+    ctx.accounts.listing.status = ListingStatus::Active;
+
+    emit!(RentalExpired {
+        listing_id: ctx.accounts.listing.listing_id,
+        agent_id: agent.id,
+        renter: rental.renter,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Withdraws an unsold listing, unlocking the agent and refunding the
+/// listing's rent to the seller. A rented-out agent must be returned first.
+pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+
+    require!(
+        matches!(listing.status, ListingStatus::Active | ListingStatus::Suspended),
+        CustomError::InvalidListingStatus
+    );
+    // Closing the listing would strand any bid escrow not yet refunded.
+    require!(listing.open_bids == 0, CustomError::AuctionHasBids);
+
+    listing.status = ListingStatus::Cancelled;
+    ctx.accounts.agent.is_listed = false;
+
+    emit!(ListingCancelled {
+        listing_id: listing.listing_id,
+        agent_id: listing.agent_id,
+        seller: listing.seller,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Reprices an open listing. For rental listings this is the price per
+/// period and applies to rentals started afterwards.
+pub fn update_listing_price(ctx: Context<ManageListing>, price: u64) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+
+    require!(price > 0, CustomError::InvalidPrice);
+    require!(listing.status != ListingStatus::Sold, CustomError::InvalidListingStatus);
+    require!(listing.highest_bidder.is_none(), CustomError::AuctionHasBids);
+
+    let old_price = listing.price;
+    listing.price = price;
+
+    emit!(ListingPriceUpdated {
+        listing_id: listing.listing_id,
+        old_price,
+        new_price: price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Hides an active listing from sale or rent while it is under review.
+pub fn suspend_listing(ctx: Context<ModerateListing>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    // Bidders' funds are escrowed against the auction, so it must run its course.
+    require!(listing.highest_bidder.is_none(), CustomError::AuctionHasBids);
+    listing.status = ListingStatus::Suspended;
+
+    emit!(ListingSuspended {
+        listing_id: listing.listing_id,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn reinstate_listing(ctx: Context<ModerateListing>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+
+    require!(listing.status == ListingStatus::Suspended, CustomError::InvalidListingStatus);
+    listing.status = ListingStatus::Active;
+
+    emit!(ListingReinstated {
+        listing_id: listing.listing_id,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
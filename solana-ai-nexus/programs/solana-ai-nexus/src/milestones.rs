@@ -0,0 +1,435 @@
+use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::reputation::*;
+use crate::state::*;
+use crate::transfer_lamports;
+
+pub const MAX_MILESTONES: usize = 8;
+
+/// Splits a task's escrowed reward into milestones that are paid out one at a
+/// time as the creator approves them.
+#[account]
+pub struct MilestonePlan {
+    pub task: Pubkey,
+    pub milestones: Vec<Milestone>,
+    pub approved_count: u8,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Milestone {
+    pub amount: u64,
+    pub deadline: i64,
+    pub status: MilestoneStatus,
+    pub result_uri: Option<String>,
+    pub approved_at: i64,
+    pub submitted_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct MilestoneInput {
+    pub amount: u64,
+    pub deadline: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum MilestoneStatus {
+    Pending,
+    Submitted,
+    Approved,
+}
+
+#[derive(Accounts)]
+pub struct AddMilestones<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + MilestonePlan::SPACE,
+        seeds = [MilestonePlan::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub plan: Account<'info, MilestonePlan>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitMilestone<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    pub task: Account<'info, Task>,
+    #[account(
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch,
+        constraint = agent.is_operator(authority.key()) @ CustomError::NotAgentOperator
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [MilestonePlan::SEED, task.key().as_ref()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, MilestonePlan>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveMilestone<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [MilestonePlan::SEED, task.key().as_ref()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, MilestonePlan>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the milestone payment; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeMilestone<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [MilestonePlan::SEED, task.key().as_ref()],
+        bump = plan.bump
+    )]
+    pub plan: Account<'info, MilestonePlan>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the milestone payment; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: UncheckedAccount<'info>,
+    /// CHECK: receives the escrow rent after the last milestone; pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMilestonePlan<'info> {
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        seeds = [MilestonePlan::SEED, task.key().as_ref()],
+        bump = plan.bump,
+        close = creator
+    )]
+    pub plan: Account<'info, MilestonePlan>,
+    /// CHECK: receives the plan rent; address is pinned to the task creator who paid it.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+}
+
+impl MilestonePlan {
+    pub const SEED: &'static [u8] = b"milestones";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // task
+                            4 + (MAX_MILESTONES * Milestone::SPACE) + // milestones
+                            1 + // approved_count
+                            1 + // bump
+                            64; // padding
+
+    pub fn is_complete(&self) -> bool {
+        self.approved_count as usize == self.milestones.len()
+    }
+}
+
+impl Milestone {
+    pub const SPACE: usize = 8 + // amount
+                            8 + // deadline
+                            1 + // status
+                            1 + 4 + MAX_RESULT_URI_LEN + // result_uri
+                            8 + // approved_at
+                            8; // submitted_at
+}
+
+#[event]
+pub struct MilestoneSubmitted {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub index: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneApproved {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub index: u8,
+    pub amount: u64,
+    // Set when approved by finalize_milestone after the review window
+    pub finalized: bool,
+    pub timestamp: i64,
+}
+
+/// Splits a pending task's reward into milestones. Amounts must add up to the
+/// reward and deadlines must be increasing and no later than the task deadline.
+pub fn add_milestones(ctx: Context<AddMilestones>, milestones: Vec<MilestoneInput>) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(task.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+    require!(
+        !milestones.is_empty() && milestones.len() <= MAX_MILESTONES,
+        CustomError::InvalidMilestones
+    );
+
+    let mut total: u64 = 0;
+    let mut previous_deadline = now;
+    for milestone in milestones.iter() {
+        require!(milestone.amount > 0, CustomError::InvalidMilestones);
+        require!(
+            milestone.deadline > previous_deadline && milestone.deadline <= task.deadline,
+            CustomError::InvalidMilestones
+        );
+        total = total.checked_add(milestone.amount).unwrap();
+        previous_deadline = milestone.deadline;
+    }
+    require!(total == task.reward, CustomError::InvalidMilestones);
+
+    let plan = &mut ctx.accounts.plan;
+    plan.task = task.key();
+    plan.milestones = milestones
+        .iter()
+        .map(|m| Milestone {
+            amount: m.amount,
+            deadline: m.deadline,
+            status: MilestoneStatus::Pending,
+            result_uri: None,
+            approved_at: 0,
+            submitted_at: 0,
+        })
+        .collect();
+    plan.approved_count = 0;
+    plan.bump = *ctx.bumps.get("plan").unwrap();
+
+    task.milestone_count = plan.milestones.len() as u8;
+
+    Ok(())
+}
+
+/// Records the agent's result for a milestone. A submitted milestone may be
+/// resubmitted until the creator approves it; each submission restarts the
+/// review window.
+pub fn submit_milestone(ctx: Context<SubmitMilestone>, index: u8, result_uri: String) -> Result<()> {
+    let task = &ctx.accounts.task;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        task.status == TaskStatus::InProgress || task.status == TaskStatus::PartiallyCompleted,
+        CustomError::InvalidTaskStatus
+    );
+    require!(result_uri.len() <= MAX_RESULT_URI_LEN, CustomError::ResultUriTooLong);
+
+    let milestone = ctx
+        .accounts
+        .plan
+        .milestones
+        .get_mut(index as usize)
+        .ok_or(CustomError::InvalidMilestoneIndex)?;
+    require!(milestone.status != MilestoneStatus::Approved, CustomError::InvalidMilestoneStatus);
+    require!(now <= milestone.deadline, CustomError::TaskDeadlinePassed);
+
+    milestone.status = MilestoneStatus::Submitted;
+    milestone.result_uri = Some(result_uri);
+    milestone.submitted_at = now;
+
+    emit!(MilestoneSubmitted {
+        task_id: task.id,
+        agent_id: ctx.accounts.agent.id,
+        index,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Pays a submitted milestone's slice of the escrow to the agent owner. The
+/// task completes when the last milestone is approved and the emptied escrow
+/// is closed back to the creator.
+pub fn approve_milestone(ctx: Context<ApproveMilestone>, index: u8) -> Result<()> {
+    release_milestone(
+        &mut ctx.accounts.task,
+        &mut ctx.accounts.agent,
+        &mut ctx.accounts.plan,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.agent_owner.to_account_info(),
+        &ctx.accounts.creator.to_account_info(),
+        index,
+        false,
+    )
+}
+
+/// Approves a submitted milestone the creator has not reviewed within the review window.
+pub fn finalize_milestone(ctx: Context<FinalizeMilestone>, index: u8) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let milestone = ctx
+        .accounts
+        .plan
+        .milestones
+        .get(index as usize)
+        .ok_or(CustomError::InvalidMilestoneIndex)?;
+
+    require!(milestone.status == MilestoneStatus::Submitted, CustomError::InvalidMilestoneStatus);
+    require!(
+        now > milestone.submitted_at.checked_add(ctx.accounts.state.review_window).unwrap(),
+        CustomError::ReviewWindowOpen
+    );
+
+    release_milestone(
+        &mut ctx.accounts.task,
+        &mut ctx.accounts.agent,
+        &mut ctx.accounts.plan,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.agent_owner.to_account_info(),
+        &ctx.accounts.creator.to_account_info(),
+        index,
+        true,
+    )
+}
+
+/// Pays every milestone that was submitted before its deadline but never
+/// reviewed, so an expiring task does not refund work delivered on time.
+/// Returns the amount paid; the caller settles the task itself.
+pub fn pay_submitted_milestones<'info>(
+    agent: &mut Agent,
+    plan: &mut MilestonePlan,
+    escrow: &mut Account<'info, TaskEscrow>,
+    agent_owner: &AccountInfo<'info>,
+    task_id: u64,
+    now: i64,
+) -> Result<u64> {
+    let mut paid: u64 = 0;
+    for index in 0..plan.milestones.len() {
+        if plan.milestones[index].status != MilestoneStatus::Submitted {
+            continue;
+        }
+        let amount = pay_milestone(agent, plan, escrow, agent_owner, index, now)?;
+        paid = paid.checked_add(amount).unwrap();
+
+        emit!(MilestoneApproved {
+            task_id,
+            agent_id: agent.id,
+            index: index as u8,
+            amount,
+            finalized: true,
+            timestamp: now,
+        });
+    }
+    Ok(paid)
+}
+
+/// Closes the plan of a finished task and returns its rent to the creator.
+/// Anyone may call it.
+pub fn close_milestone_plan(ctx: Context<CloseMilestonePlan>) -> Result<()> {
+    require!(ctx.accounts.task.is_finished(), CustomError::InvalidTaskStatus);
+    Ok(())
+}
+
+/// Marks a milestone task whose last milestone has been paid as completed.
+pub fn complete_milestone_task(task: &mut Task, agent: &mut Agent, now: i64) -> Result<()> {
+    task.status = TaskStatus::Completed;
+    task.completed_at = now;
+    agent.tasks_completed = agent.tasks_completed.checked_add(1).unwrap();
+    agent.open_tasks = agent.open_tasks.saturating_sub(1);
+    apply_outcome(agent, ReputationOutcome::Completed { reward: 0 }, now)
+}
+
+fn pay_milestone<'info>(
+    agent: &mut Agent,
+    plan: &mut MilestonePlan,
+    escrow: &mut Account<'info, TaskEscrow>,
+    agent_owner: &AccountInfo<'info>,
+    index: usize,
+    now: i64,
+) -> Result<u64> {
+    let milestone = &mut plan.milestones[index];
+    let amount = milestone.amount;
+    milestone.status = MilestoneStatus::Approved;
+    milestone.approved_at = now;
+    plan.approved_count = plan.approved_count.checked_add(1).unwrap();
+
+    escrow.amount = escrow.amount.checked_sub(amount).unwrap();
+    transfer_lamports(&escrow.to_account_info(), agent_owner, amount)?;
+    apply_outcome(agent, ReputationOutcome::MilestoneApproved { amount }, now)?;
+
+    Ok(amount)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn release_milestone<'info>(
+    task: &mut Account<'info, Task>,
+    agent: &mut Account<'info, Agent>,
+    plan: &mut Account<'info, MilestonePlan>,
+    escrow: &mut Account<'info, TaskEscrow>,
+    agent_owner: &AccountInfo<'info>,
+    creator: &AccountInfo<'info>,
+    index: u8,
+    finalized: bool,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        task.status == TaskStatus::InProgress || task.status == TaskStatus::PartiallyCompleted,
+        CustomError::InvalidTaskStatus
+    );
+
+    let milestone = plan
+        .milestones
+        .get(index as usize)
+        .ok_or(CustomError::InvalidMilestoneIndex)?;
+    require!(milestone.status == MilestoneStatus::Submitted, CustomError::InvalidMilestoneStatus);
+
+    let amount = pay_milestone(agent, plan, escrow, agent_owner, index as usize, now)?;
+
+    if plan.is_complete() {
+        complete_milestone_task(task, agent, now)?;
+        escrow.close(creator.clone())?;
+    } else {
+        task.status = TaskStatus::PartiallyCompleted;
+    }
+
+    emit!(MilestoneApproved {
+        task_id: task.id,
+        agent_id: agent.id,
+        index,
+        amount,
+        finalized,
+        timestamp: now,
+    });
+
+    Ok(())
+}
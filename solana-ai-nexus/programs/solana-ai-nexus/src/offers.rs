@@ -0,0 +1,688 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use crate::errors::CustomError;
+use crate::marketplace::*;
+use crate::state::{Agent, State};
+
+// How long after an auction ends the winner must wait for settlement before
+// they may take their bid back instead.
+pub const AUCTION_SETTLEMENT_TIMEOUT: i64 = 7 * 24 * 60 * 60;
+
+/// An offer below the list price on a sale listing. The offered tokens sit in
+/// an escrow token account owned by the marketplace PDA until the seller
+/// accepts or the buyer withdraws.
+#[account]
+pub struct ListingOffer {
+    pub listing: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+/// A bidder's standing bid on an auction listing. Raising a bid tops up the
+/// same escrow; bids that are no longer the highest can be refunded by anyone,
+/// and the winning bid once the auction has gone unsettled for too long.
+#[account]
+pub struct AuctionBid {
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub placed_at: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        constraint = listing.listing_type == ListingType::Sale @ CustomError::InvalidListingType
+    )]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + ListingOffer::SPACE,
+        seeds = [ListingOffer::SEED, listing.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, ListingOffer>,
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [ListingOffer::ESCROW_SEED, offer.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub escrow: Account<'info, TokenAccount>,
+    #[account(address = marketplace.payment_mint @ CustomError::InvalidPaymentMint)]
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(seeds = [Marketplace::SEED], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [ListingOffer::SEED, offer.listing.as_ref(), buyer.key().as_ref()],
+        bump = offer.bump,
+        has_one = buyer @ CustomError::Unauthorized,
+        close = buyer
+    )]
+    pub offer: Account<'info, ListingOffer>,
+    #[account(mut, seeds = [ListingOffer::ESCROW_SEED, offer.key().as_ref()], bump)]
+    pub escrow: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key() @ CustomError::Unauthorized
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        has_one = seller @ CustomError::Unauthorized,
+        constraint = listing.listing_type == ListingType::Sale @ CustomError::InvalidListingType
+    )]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch,
+        constraint = agent.owner == listing.seller @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [ListingOffer::SEED, listing.key().as_ref(), offer.buyer.as_ref()],
+        bump = offer.bump,
+        close = buyer
+    )]
+    pub offer: Account<'info, ListingOffer>,
+    #[account(mut, seeds = [ListingOffer::ESCROW_SEED, offer.key().as_ref()], bump)]
+    pub escrow: Account<'info, TokenAccount>,
+    /// CHECK: becomes the agent owner and receives the offer's rent; address is pinned to the buyer.
+    #[account(mut, address = offer.buyer @ CustomError::Unauthorized)]
+    pub buyer: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == listing.seller @ CustomError::Unauthorized,
+        constraint = seller_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [Marketplace::TREASURY_SEED], bump)]
+    pub treasury: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceAuctionBid<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump,
+        constraint = marketplace.is_active @ CustomError::MarketplaceInactive
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        constraint = listing.listing_type == ListingType::Auction @ CustomError::InvalidListingType,
+        constraint = listing.seller != bidder.key() @ CustomError::SellerCannotBid
+    )]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + AuctionBid::SPACE,
+        seeds = [AuctionBid::SEED, listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, AuctionBid>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        seeds = [AuctionBid::ESCROW_SEED, bid.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub escrow: Account<'info, TokenAccount>,
+    #[account(address = marketplace.payment_mint @ CustomError::InvalidPaymentMint)]
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        constraint = bidder_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundAuctionBid<'info> {
+    #[account(seeds = [Marketplace::SEED], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        seeds = [AuctionBid::SEED, listing.key().as_ref(), bid.bidder.as_ref()],
+        bump = bid.bump,
+        close = bidder
+    )]
+    pub bid: Account<'info, AuctionBid>,
+    #[account(mut, seeds = [AuctionBid::ESCROW_SEED, bid.key().as_ref()], bump)]
+    pub escrow: Account<'info, TokenAccount>,
+    /// CHECK: receives the bid account's rent; address is pinned to the bidder.
+    #[account(mut, address = bid.bidder @ CustomError::Unauthorized)]
+    pub bidder: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = bidder_token_account.owner == bid.bidder @ CustomError::Unauthorized
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    #[account(
+        mut,
+        seeds = [Marketplace::SEED],
+        bump = marketplace.bump
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        constraint = listing.listing_type == ListingType::Auction @ CustomError::InvalidListingType
+    )]
+    pub listing: Account<'info, AgentListing>,
+    #[account(
+        mut,
+        constraint = agent.id == listing.agent_id @ CustomError::AgentIdMismatch,
+        constraint = agent.owner == listing.seller @ CustomError::Unauthorized
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [AuctionBid::SEED, listing.key().as_ref(), bid.bidder.as_ref()],
+        bump = bid.bump,
+        close = winner
+    )]
+    pub bid: Account<'info, AuctionBid>,
+    #[account(mut, seeds = [AuctionBid::ESCROW_SEED, bid.key().as_ref()], bump)]
+    pub escrow: Account<'info, TokenAccount>,
+    /// CHECK: becomes the agent owner and receives the bid's rent; address is pinned to the bidder.
+    #[account(mut, address = bid.bidder @ CustomError::Unauthorized)]
+    pub winner: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == listing.seller @ CustomError::Unauthorized,
+        constraint = seller_token_account.mint == marketplace.payment_mint @ CustomError::InvalidPaymentMint
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [Marketplace::TREASURY_SEED], bump)]
+    pub treasury: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl ListingOffer {
+    pub const SEED: &'static [u8] = b"offer";
+    pub const ESCROW_SEED: &'static [u8] = b"offer_escrow";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // listing
+                            32 + // buyer
+                            8 + // amount
+                            8 + // created_at
+                            1 + // bump
+                            64; // padding
+}
+
+impl AuctionBid {
+    pub const SEED: &'static [u8] = b"auction_bid";
+    pub const ESCROW_SEED: &'static [u8] = b"auction_bid_escrow";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // listing
+                            32 + // bidder
+                            8 + // amount
+                            8 + // placed_at
+                            1 + // bump
+                            64; // padding
+}
+
+#[event]
+pub struct OfferMade {
+    pub listing_id: u64,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferCancelled {
+    pub listing: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferAccepted {
+    pub listing_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionBidPlaced {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionBidRefunded {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionSettled {
+    pub listing_id: u64,
+    pub winner: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+fn close_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, TokenAccount>,
+    rent_receiver: AccountInfo<'info>,
+    marketplace: &Account<'info, Marketplace>,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[Marketplace::SEED, &[marketplace.bump]];
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::CloseAccount {
+            account: escrow.to_account_info(),
+            destination: rent_receiver,
+            authority: marketplace.to_account_info(),
+        },
+        &[seeds],
+    ))
+}
+
+/// Returns an escrow's remaining tokens to `destination` and closes it,
+/// refunding its rent to `rent_receiver`. Signed by the marketplace PDA.
+fn release_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, TokenAccount>,
+    destination: &Account<'info, TokenAccount>,
+    rent_receiver: AccountInfo<'info>,
+    marketplace: &Account<'info, Marketplace>,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[Marketplace::SEED, &[marketplace.bump]];
+
+    if escrow.amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::Transfer {
+                    from: escrow.to_account_info(),
+                    to: destination.to_account_info(),
+                    authority: marketplace.to_account_info(),
+                },
+                &[seeds],
+            ),
+            escrow.amount,
+        )?;
+    }
+    close_escrow(token_program, escrow, rent_receiver, marketplace)
+}
+
+/// Pays an escrowed sale price to the seller and treasury, then closes the
+/// escrow with its rent going to `rent_receiver`.
+#[allow(clippy::too_many_arguments)]
+fn settle_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, TokenAccount>,
+    seller_token_account: &Account<'info, TokenAccount>,
+    treasury: &Account<'info, TokenAccount>,
+    rent_receiver: AccountInfo<'info>,
+    marketplace: &Account<'info, Marketplace>,
+    price: u64,
+    fee: u64,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[Marketplace::SEED, &[marketplace.bump]];
+
+    pay_with_fee(
+        token_program,
+        escrow.to_account_info(),
+        marketplace.to_account_info(),
+        &[seeds],
+        seller_token_account,
+        treasury,
+        price,
+        fee,
+    )?;
+    close_escrow(token_program, escrow, rent_receiver, marketplace)
+}
+
+/// Escrows an offer below the list price on an active sale listing.
+pub fn make_offer(ctx: Context<MakeOffer>, amount: u64) -> Result<()> {
+    let listing = &ctx.accounts.listing;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(amount > 0, CustomError::InvalidPrice);
+    require!(amount < listing.price, CustomError::OfferTooHigh);
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.escrow.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    let offer = &mut ctx.accounts.offer;
+    offer.listing = listing.key();
+    offer.buyer = ctx.accounts.buyer.key();
+    offer.amount = amount;
+    offer.created_at = now;
+    offer.bump = *ctx.bumps.get("offer").unwrap();
+
+    emit!(OfferMade {
+        listing_id: listing.listing_id,
+        buyer: offer.buyer,
+        amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Withdraws an offer and its escrow. Works whatever became of the listing.
+pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+    release_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.buyer_token_account,
+        ctx.accounts.buyer.to_account_info(),
+        &ctx.accounts.marketplace,
+    )?;
+
+    let offer = &ctx.accounts.offer;
+    emit!(OfferCancelled {
+        listing: offer.listing,
+        buyer: offer.buyer,
+        amount: offer.amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Sells the agent to the offering buyer at the offered amount.
+pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+    let amount = ctx.accounts.offer.amount;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(ctx.accounts.agent.open_tasks == 0, CustomError::AgentHasOpenTasks);
+
+    let fee_amount = ctx.accounts.marketplace.fee_for(amount);
+    settle_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.seller_token_account,
+        &ctx.accounts.treasury,
+        ctx.accounts.buyer.to_account_info(),
+        &ctx.accounts.marketplace,
+        amount,
+        fee_amount,
+    )?;
+
+    listing.status = ListingStatus::Sold;
+    let marketplace = &mut ctx.accounts.marketplace;
+    marketplace.total_volume = marketplace.total_volume.checked_add(amount).unwrap();
+    transfer_agent(&mut ctx.accounts.agent, ctx.accounts.buyer.key(), now);
+
+    emit!(OfferAccepted {
+        listing_id: listing.listing_id,
+        buyer: ctx.accounts.buyer.key(),
+        seller: listing.seller,
+        amount,
+        fee: fee_amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Lists the agent in an English auction. `reserve_price` is the lowest
+/// acceptable first bid; each later bid must beat the highest by at least
+/// `min_bid_increment`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_auction_listing(
+    ctx: Context<CreateListing>,
+    agent_id: u64,
+    reserve_price: u64,
+    min_bid_increment: u64,
+    ends_at: i64,
+    extension_window: i64,
+    description: String,
+) -> Result<()> {
+    require!(
+        min_bid_increment > 0 && extension_window >= 0 && ends_at > Clock::get()?.unix_timestamp,
+        CustomError::InvalidAuctionTerms
+    );
+
+    open_listing(ctx.accounts, agent_id, reserve_price, description, ListingType::Auction)?;
+
+    let listing = &mut ctx.accounts.listing;
+    listing.min_bid_increment = min_bid_increment;
+    listing.ends_at = ends_at;
+    listing.extension_window = extension_window;
+    Ok(())
+}
+
+/// Places or raises a bid to `amount`, escrowing the difference. A bid inside
+/// the extension window pushes the end time back.
+pub fn place_auction_bid(ctx: Context<PlaceAuctionBid>, amount: u64) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+    let bid = &mut ctx.accounts.bid;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(now < listing.ends_at, CustomError::AuctionEnded);
+    let minimum = match listing.highest_bidder {
+        Some(_) => listing.highest_bid.checked_add(listing.min_bid_increment).unwrap(),
+        None => listing.price,
+    };
+    require!(amount >= minimum, CustomError::BidTooLow);
+    if bid.amount == 0 {
+        listing.open_bids = listing.open_bids.checked_add(1).unwrap();
+    }
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.bidder_token_account.to_account_info(),
+                to: ctx.accounts.escrow.to_account_info(),
+                authority: ctx.accounts.bidder.to_account_info(),
+            },
+        ),
+        amount.checked_sub(bid.amount).unwrap(),
+    )?;
+
+    bid.listing = listing.key();
+    bid.bidder = ctx.accounts.bidder.key();
+    bid.amount = amount;
+    bid.placed_at = now;
+    bid.bump = *ctx.bumps.get("bid").unwrap();
+
+    listing.highest_bid = amount;
+    listing.highest_bidder = Some(bid.bidder);
+    if listing.ends_at - now < listing.extension_window {
+        listing.ends_at = now.checked_add(listing.extension_window).unwrap();
+    }
+
+    emit!(AuctionBidPlaced {
+        listing_id: listing.listing_id,
+        bidder: bid.bidder,
+        amount,
+        ends_at: listing.ends_at,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Permissionless: returns an outbid bidder's escrow and closes their bid. The
+/// winning bid is only refunded once the auction has gone unsettled for
+/// `AUCTION_SETTLEMENT_TIMEOUT`; the auction is then left without bids so the
+/// seller can cancel it.
+pub fn refund_auction_bid(ctx: Context<RefundAuctionBid>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+    let bid = &ctx.accounts.bid;
+    let now = Clock::get()?.unix_timestamp;
+
+    if listing.highest_bidder == Some(bid.bidder) {
+        require!(
+            listing.status == ListingStatus::Active
+                && now > listing.ends_at.checked_add(AUCTION_SETTLEMENT_TIMEOUT).unwrap(),
+            CustomError::BidIsWinning
+        );
+        listing.highest_bidder = None;
+        listing.highest_bid = 0;
+    }
+    listing.open_bids = listing.open_bids.saturating_sub(1);
+
+    release_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.bidder_token_account,
+        ctx.accounts.bidder.to_account_info(),
+        &ctx.accounts.marketplace,
+    )?;
+
+    emit!(AuctionBidRefunded {
+        listing_id: listing.listing_id,
+        bidder: bid.bidder,
+        amount: bid.amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Permissionless once the auction has ended: pays the highest bid to the
+/// seller and treasury and hands the agent to the winner. Auctions that
+/// ended without bids are closed by the seller with `cancel_listing`.
+pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+    let listing = &mut ctx.accounts.listing;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(listing.status == ListingStatus::Active, CustomError::InvalidListingStatus);
+    require!(now >= listing.ends_at, CustomError::AuctionNotEnded);
+    require!(
+        listing.highest_bidder == Some(ctx.accounts.bid.bidder),
+        CustomError::NotWinningBid
+    );
+    require!(ctx.accounts.agent.open_tasks == 0, CustomError::AgentHasOpenTasks);
+
+    let price = listing.highest_bid;
+    let fee_amount = ctx.accounts.marketplace.fee_for(price);
+    settle_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.seller_token_account,
+        &ctx.accounts.treasury,
+        ctx.accounts.winner.to_account_info(),
+        &ctx.accounts.marketplace,
+        price,
+        fee_amount,
+    )?;
+
+    listing.status = ListingStatus::Sold;
+    listing.open_bids = listing.open_bids.saturating_sub(1);
+    let marketplace = &mut ctx.accounts.marketplace;
+    marketplace.total_volume = marketplace.total_volume.checked_add(price).unwrap();
+    transfer_agent(&mut ctx.accounts.agent, ctx.accounts.winner.key(), now);
+
+    emit!(AuctionSettled {
+        listing_id: listing.listing_id,
+        winner: ctx.accounts.winner.key(),
+        seller: listing.seller,
+        price,
+        fee: fee_amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
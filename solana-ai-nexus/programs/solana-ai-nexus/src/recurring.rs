@@ -0,0 +1,297 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use crate::errors::CustomError;
+use crate::state::*;
+use crate::transfer_lamports;
+
+/// A template that spawns a pending task every `interval` seconds. It holds
+/// the reward for every remaining run plus the rent for each spawned task and
+/// escrow, which is refunded to whoever cranks the spawn.
+#[account]
+pub struct RecurringTask {
+    pub id: u64,
+    pub creator: Pubkey,
+    pub required_capabilities: u64,
+    pub description: String,
+    pub reward: u64, // Per run
+    pub interval: i64,
+    pub run_duration: i64, // Seconds from spawn to each child task's deadline
+    pub max_runs: u32,
+    pub runs_spawned: u32,
+    pub next_run_at: i64,
+    pub spawn_rent: u64,
+    pub is_paused: bool,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct CreateRecurringTask<'info> {
+    #[account(
+        mut,
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + RecurringTask::SPACE,
+        seeds = [RecurringTask::SEED, &state.recurring_count.to_le_bytes()],
+        bump
+    )]
+    pub template: Account<'info, RecurringTask>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SpawnRecurringTask<'info> {
+    #[account(
+        mut,
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [RecurringTask::SEED, &template.id.to_le_bytes()],
+        bump = template.bump
+    )]
+    pub template: Account<'info, RecurringTask>,
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + Task::SPACE,
+        seeds = [Task::SEED, &state.task_count.to_le_bytes()],
+        bump
+    )]
+    pub task: Account<'info, Task>,
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + TaskEscrow::SPACE,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageRecurringTask<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub template: Account<'info, RecurringTask>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRecurringTask<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized, close = creator)]
+    pub template: Account<'info, RecurringTask>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+}
+
+impl RecurringTask {
+    pub const SEED: &'static [u8] = b"recurring";
+    pub const SPACE: usize = 8 + // discriminator
+                            8 + // id
+                            32 + // creator
+                            8 + // required_capabilities
+                            4 + MAX_TASK_DESCRIPTION_LEN + // description
+                            8 + // reward
+                            8 + // interval
+                            8 + // run_duration
+                            4 + // max_runs
+                            4 + // runs_spawned
+                            8 + // next_run_at
+                            8 + // spawn_rent
+                            1 + // is_paused
+                            1 + // bump
+                            64; // padding
+}
+
+#[event]
+pub struct RecurringTaskCreated {
+    pub template_id: u64,
+    pub creator: Pubkey,
+    pub reward: u64,
+    pub interval: i64,
+    pub max_runs: u32,
+    pub funded: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecurringTaskSpawned {
+    pub template_id: u64,
+    pub task_id: u64,
+    pub run: u32,
+    pub cranker: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecurringTaskCancelled {
+    pub template_id: u64,
+    pub runs_spawned: u32,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+/// Creates a template and prefunds it with the reward and spawn rent for
+/// every run. The first task can be spawned at `first_run_at`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_recurring_task(
+    ctx: Context<CreateRecurringTask>,
+    description: String,
+    reward: u64,
+    interval: i64,
+    run_duration: i64,
+    max_runs: u32,
+    first_run_at: i64,
+    required_capabilities: u64,
+) -> Result<()> {
+    require!(
+        description.len() <= MAX_TASK_DESCRIPTION_LEN,
+        CustomError::DescriptionTooLong
+    );
+    Agent::validate_capabilities(required_capabilities)?;
+    require!(reward > 0, CustomError::InvalidReward);
+    require!(interval > 0 && run_duration > 0, CustomError::InvalidInterval);
+    require!(max_runs > 0, CustomError::InvalidMaxRuns);
+
+    let now = Clock::get()?.unix_timestamp;
+    let rent = Rent::get()?;
+    let spawn_rent = rent
+        .minimum_balance(8 + Task::SPACE)
+        .checked_add(rent.minimum_balance(8 + TaskEscrow::SPACE))
+        .unwrap();
+    let funded = reward
+        .checked_add(spawn_rent)
+        .unwrap()
+        .checked_mul(max_runs as u64)
+        .unwrap();
+
+    let state = &mut ctx.accounts.state;
+    let template = &mut ctx.accounts.template;
+    template.id = state.recurring_count;
+    template.creator = ctx.accounts.creator.key();
+    template.description = description;
+    template.required_capabilities = required_capabilities;
+    template.reward = reward;
+    template.interval = interval;
+    template.run_duration = run_duration;
+    template.max_runs = max_runs;
+    template.runs_spawned = 0;
+    template.next_run_at = first_run_at.max(now);
+    template.spawn_rent = spawn_rent;
+    template.is_paused = false;
+    template.bump = *ctx.bumps.get("template").unwrap();
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.creator.to_account_info(),
+                to: template.to_account_info(),
+            },
+        ),
+        funded,
+    )?;
+
+    state.recurring_count = state.recurring_count.checked_add(1).unwrap();
+
+    emit!(RecurringTaskCreated {
+        template_id: template.id,
+        creator: template.creator,
+        reward,
+        interval,
+        max_runs,
+        funded,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Permissionless crank that spawns the next child task once it is due. The
+/// cranker pays the rent for the new accounts and is refunded from the template.
+pub fn spawn_recurring_task(ctx: Context<SpawnRecurringTask>) -> Result<()> {
+    let state = &mut ctx.accounts.state;
+    let template = &mut ctx.accounts.template;
+    let task = &mut ctx.accounts.task;
+    let escrow = &mut ctx.accounts.escrow;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(!template.is_paused, CustomError::RecurringTaskPaused);
+    require!(template.runs_spawned < template.max_runs, CustomError::RecurringRunsExhausted);
+    require!(now >= template.next_run_at, CustomError::RecurringRunNotDue);
+
+    task.initialize(
+        state.task_count,
+        template.creator,
+        template.description.clone(),
+        template.reward,
+        now.checked_add(template.run_duration).unwrap(),
+        *ctx.bumps.get("task").unwrap(),
+    );
+    task.required_capabilities = template.required_capabilities;
+
+    escrow.task = task.key();
+    escrow.creator = task.creator;
+    escrow.amount = template.reward;
+    escrow.bump = *ctx.bumps.get("escrow").unwrap();
+
+    transfer_lamports(&template.to_account_info(), &escrow.to_account_info(), template.reward)?;
+    transfer_lamports(
+        &template.to_account_info(),
+        &ctx.accounts.cranker.to_account_info(),
+        template.spawn_rent,
+    )?;
+
+    // Runs missed while the crank was idle or the template paused are skipped
+    // rather than spawned back to back.
+    let missed = (now - template.next_run_at) / template.interval;
+    template.next_run_at = template
+        .next_run_at
+        .checked_add(template.interval.checked_mul(missed + 1).unwrap())
+        .unwrap();
+    template.runs_spawned = template.runs_spawned.checked_add(1).unwrap();
+    state.task_count = state.task_count.checked_add(1).unwrap();
+
+    emit!(RecurringTaskSpawned {
+        template_id: template.id,
+        task_id: task.id,
+        run: template.runs_spawned,
+        cranker: ctx.accounts.cranker.key(),
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn set_recurring_task_paused(ctx: Context<ManageRecurringTask>, paused: bool) -> Result<()> {
+    ctx.accounts.template.is_paused = paused;
+    Ok(())
+}
+
+/// Closes the template, refunding the unspent rewards and rent to the creator.
+/// Tasks already spawned are unaffected.
+pub fn cancel_recurring_task(ctx: Context<CancelRecurringTask>) -> Result<()> {
+    let template = &ctx.accounts.template;
+
+    emit!(RecurringTaskCancelled {
+        template_id: template.id,
+        runs_spawned: template.runs_spawned,
+        refunded: template.to_account_info().lamports(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
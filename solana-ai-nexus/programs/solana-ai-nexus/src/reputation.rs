@@ -0,0 +1,182 @@
+use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::events::ReputationUpdateEvent;
+use crate::state::*;
+
+// Reputation is derived from task outcomes rather than set by hand. Every
+// outcome first decays the current score, then adds or removes points:
+//
+// - settled completion: COMPLETION_POINTS, plus one point per REWARD_POINTS_UNIT
+//   lamports earned, capped at MAX_REWARD_POINTS
+// - approved milestone: the reward points for its amount; COMPLETION_POINTS
+//   follow once the last milestone is approved
+// - creator rating: (rating - NEUTRAL_RATING) * RATING_POINTS
+// - task expired before delivery: -EXPIRY_REPUTATION_PENALTY
+// - task given back by the agent after assignment: -CANCELLATION_REPUTATION_PENALTY
+// - dispute ruling: -DISPUTE_REPUTATION_PENALTY scaled by the creator's share
+//
+// The score halves every REPUTATION_HALF_LIFE seconds, interpolated linearly
+// within a half-life, so an idle agent's reputation fades instead of sticking.
+pub const COMPLETION_POINTS: i64 = 10;
+pub const REWARD_POINTS_UNIT: u64 = 100_000_000;
+pub const MAX_REWARD_POINTS: i64 = 10;
+pub const MIN_RATING: u8 = 1;
+pub const MAX_RATING: u8 = 5;
+pub const NEUTRAL_RATING: u8 = 3;
+pub const RATING_POINTS: i64 = 2;
+pub const EXPIRY_REPUTATION_PENALTY: i64 = 5;
+pub const CANCELLATION_REPUTATION_PENALTY: i64 = 3;
+pub const DISPUTE_REPUTATION_PENALTY: i64 = 10;
+pub const REPUTATION_HALF_LIFE: i64 = 90 * 24 * 60 * 60;
+pub const MAX_OVERRIDE_REASON_LEN: usize = 128;
+
+#[derive(Clone, Copy)]
+pub enum ReputationOutcome {
+    Completed { reward: u64 },
+    MilestoneApproved { amount: u64 },
+    Rated { rating: u8 },
+    Expired,
+    Cancelled,
+    DisputeLost { creator_share_bps: u16 },
+}
+
+fn reward_points(reward: u64) -> i64 {
+    (reward / REWARD_POINTS_UNIT).min(MAX_REWARD_POINTS as u64) as i64
+}
+
+impl ReputationOutcome {
+    fn points(&self) -> i64 {
+        match *self {
+            ReputationOutcome::Completed { reward } => COMPLETION_POINTS + reward_points(reward),
+            ReputationOutcome::MilestoneApproved { amount } => reward_points(amount),
+            ReputationOutcome::Rated { rating } => {
+                (rating as i64 - NEUTRAL_RATING as i64) * RATING_POINTS
+            }
+            ReputationOutcome::Expired => -EXPIRY_REPUTATION_PENALTY,
+            ReputationOutcome::Cancelled => -CANCELLATION_REPUTATION_PENALTY,
+            ReputationOutcome::DisputeLost { creator_share_bps } => {
+                -(DISPUTE_REPUTATION_PENALTY * creator_share_bps as i64 / BPS_DENOMINATOR as i64)
+            }
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct RateTask<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DecayReputation<'info> {
+    #[account(mut)]
+    pub agent: Account<'info, Agent>,
+}
+
+#[event]
+pub struct ReputationOverrideEvent {
+    pub agent_id: u64,
+    pub authority: Pubkey,
+    pub old_score: u32,
+    pub new_score: u32,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+/// Applies the half-life decay for `elapsed` seconds to `score`.
+pub fn decayed_score(score: u32, elapsed: i64) -> u32 {
+    if elapsed <= 0 {
+        return score;
+    }
+    let halvings = elapsed / REPUTATION_HALF_LIFE;
+    if halvings >= 32 {
+        return 0;
+    }
+    let halved = (score >> halvings) as u64;
+    let remainder = (elapsed % REPUTATION_HALF_LIFE) as u64;
+    let fade = halved * remainder / (2 * REPUTATION_HALF_LIFE as u64);
+    (halved - fade) as u32
+}
+
+/// Decays the agent's score to `now`, applies `outcome` and records its counters.
+pub fn apply_outcome(agent: &mut Agent, outcome: ReputationOutcome, now: i64) -> Result<()> {
+    let old_score = agent.reputation_score;
+    let decayed = decayed_score(old_score, now - agent.reputation_updated_at);
+
+    match outcome {
+        ReputationOutcome::Completed { reward: earned }
+        | ReputationOutcome::MilestoneApproved { amount: earned } => {
+            agent.total_earned = agent.total_earned.checked_add(earned).unwrap();
+        }
+        ReputationOutcome::Rated { rating } => {
+            agent.rating_sum = agent.rating_sum.checked_add(rating as u32).unwrap();
+            agent.rating_count = agent.rating_count.checked_add(1).unwrap();
+        }
+        ReputationOutcome::Expired => {
+            agent.tasks_expired = agent.tasks_expired.checked_add(1).unwrap();
+        }
+        ReputationOutcome::Cancelled => {
+            agent.tasks_cancelled = agent.tasks_cancelled.checked_add(1).unwrap();
+        }
+        ReputationOutcome::DisputeLost { creator_share_bps } => {
+            if creator_share_bps as u64 * 2 > BPS_DENOMINATOR {
+                agent.disputes_lost = agent.disputes_lost.checked_add(1).unwrap();
+            }
+        }
+    }
+
+    let new_score = (decayed as i64 + outcome.points()).clamp(0, u32::MAX as i64) as u32;
+    agent.reputation_score = new_score;
+    agent.reputation_updated_at = now;
+
+    emit!(ReputationUpdateEvent {
+        agent_id: agent.id,
+        old_score,
+        new_score,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn rate_task(ctx: Context<RateTask>, rating: u8) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+
+    require!(
+        task.status == TaskStatus::Completed || task.status == TaskStatus::Resolved,
+        CustomError::InvalidTaskStatus
+    );
+    require!(task.rating == 0, CustomError::TaskAlreadyRated);
+    require!((MIN_RATING..=MAX_RATING).contains(&rating), CustomError::InvalidRating);
+
+    task.rating = rating;
+    apply_outcome(
+        &mut ctx.accounts.agent,
+        ReputationOutcome::Rated { rating },
+        Clock::get()?.unix_timestamp,
+    )
+}
+
+pub fn decay_reputation(ctx: Context<DecayReputation>) -> Result<()> {
+    let agent = &mut ctx.accounts.agent;
+    let now = Clock::get()?.unix_timestamp;
+
+    let old_score = agent.reputation_score;
+    agent.reputation_score = decayed_score(old_score, now - agent.reputation_updated_at);
+    agent.reputation_updated_at = now;
+
+    emit!(ReputationUpdateEvent {
+        agent_id: agent.id,
+        old_score,
+        new_score: agent.reputation_score,
+        timestamp: now,
+    });
+
+    Ok(())
+}
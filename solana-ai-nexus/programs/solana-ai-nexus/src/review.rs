@@ -0,0 +1,188 @@
+use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::reputation::*;
+use crate::state::*;
+use crate::team::*;
+use crate::transfer_lamports;
+
+pub const DEFAULT_REVIEW_WINDOW: i64 = 3 * 24 * 60 * 60;
+
+#[derive(Accounts)]
+pub struct ApproveResult<'info> {
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the reward; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RejectResult<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, has_one = creator @ CustomError::Unauthorized)]
+    pub task: Account<'info, Task>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeTask<'info> {
+    #[account(seeds = [State::SEED], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        constraint = task.agent_id == Some(agent.id) @ CustomError::AgentTaskMismatch
+    )]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [TaskEscrow::SEED, task.key().as_ref()],
+        bump = escrow.bump,
+        close = creator
+    )]
+    pub escrow: Account<'info, TaskEscrow>,
+    /// CHECK: receives the escrow rent; address is pinned to the task creator.
+    #[account(mut, address = task.creator @ CustomError::Unauthorized)]
+    pub creator: UncheckedAccount<'info>,
+    /// CHECK: receives the reward; address is pinned to the agent owner.
+    #[account(mut, address = agent.owner @ CustomError::Unauthorized)]
+    pub agent_owner: UncheckedAccount<'info>,
+}
+
+#[event]
+pub struct ResultApproved {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub amount: u64,
+    // Set when approved by finalize_task after the review window
+    pub finalized: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ResultRejected {
+    pub task_id: u64,
+    pub agent_id: u64,
+    pub reason_uri: String,
+    pub timestamp: i64,
+}
+
+/// Pays the escrowed reward to the agent owner, or splits it across the team,
+/// and completes the task. The escrow's rent goes back to the creator when the
+/// context closes it.
+fn settle_result<'info>(
+    task: &mut Account<'info, Task>,
+    agent: &mut Agent,
+    escrow: &mut Account<'info, TaskEscrow>,
+    agent_owner: &AccountInfo<'info>,
+    remaining: &[AccountInfo<'info>],
+    finalized: bool,
+    now: i64,
+) -> Result<()> {
+    let amount = escrow.amount;
+    escrow.amount = 0;
+    let team_paid = if task.has_team() {
+        pay_team_members(task.key(), &escrow.to_account_info(), amount, remaining, now)?
+    } else {
+        0
+    };
+    let lead_amount = amount.checked_sub(team_paid).unwrap();
+    transfer_lamports(&escrow.to_account_info(), agent_owner, lead_amount)?;
+
+    task.status = TaskStatus::Completed;
+    task.completed_at = now;
+    agent.tasks_completed = agent.tasks_completed.checked_add(1).unwrap();
+    agent.open_tasks = agent.open_tasks.saturating_sub(1);
+    apply_outcome(agent, ReputationOutcome::Completed { reward: lead_amount }, now)?;
+
+    emit!(ResultApproved {
+        task_id: task.id,
+        agent_id: agent.id,
+        amount,
+        finalized,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Team tasks pass the team and its members' `(agent, owner)` pairs as remaining
+/// accounts so the reward is split in the same instruction.
+pub fn approve_result<'info>(ctx: Context<'_, '_, '_, 'info, ApproveResult<'info>>) -> Result<()> {
+    require!(ctx.accounts.task.status == TaskStatus::Submitted, CustomError::InvalidTaskStatus);
+
+    settle_result(
+        &mut ctx.accounts.task,
+        &mut ctx.accounts.agent,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.agent_owner.to_account_info(),
+        ctx.remaining_accounts,
+        false,
+        Clock::get()?.unix_timestamp,
+    )
+}
+
+/// Sends a submitted result back to the agent, who may resubmit before the
+/// deadline or dispute the rejection within the dispute window.
+pub fn reject_result(ctx: Context<RejectResult>, reason_uri: String) -> Result<()> {
+    let task = &mut ctx.accounts.task;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(task.status == TaskStatus::Submitted, CustomError::InvalidTaskStatus);
+    require!(
+        now <= task.submitted_at.checked_add(ctx.accounts.state.review_window).unwrap(),
+        CustomError::ReviewWindowClosed
+    );
+    require!(reason_uri.len() <= MAX_REJECTION_URI_LEN, CustomError::RejectionUriTooLong);
+
+    task.status = TaskStatus::Rejected;
+    task.rejection_uri = Some(reason_uri.clone());
+    task.rejected_at = now;
+
+    emit!(ResultRejected {
+        task_id: task.id,
+        agent_id: task.agent_id.unwrap(),
+        reason_uri,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Approves a submitted result the creator has not reviewed within the review window.
+pub fn finalize_task<'info>(ctx: Context<'_, '_, '_, 'info, FinalizeTask<'info>>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let task = &ctx.accounts.task;
+
+    require!(task.status == TaskStatus::Submitted, CustomError::InvalidTaskStatus);
+    require!(
+        now > task.submitted_at.checked_add(ctx.accounts.state.review_window).unwrap(),
+        CustomError::ReviewWindowOpen
+    );
+
+    settle_result(
+        &mut ctx.accounts.task,
+        &mut ctx.accounts.agent,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.agent_owner.to_account_info(),
+        ctx.remaining_accounts,
+        true,
+        now,
+    )
+}
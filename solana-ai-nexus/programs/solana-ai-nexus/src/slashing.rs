@@ -0,0 +1,104 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, TokenAccount};
+use crate::errors::CustomError;
+use crate::state::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum SlashReason {
+    MissedDeadline,
+    LostDispute,
+}
+
+#[event]
+pub struct SlashEvent {
+    pub agent_id: u64,
+    pub task_id: u64,
+    pub reason: SlashReason,
+    pub amount: u64,
+    pub creator_amount: u64,
+    pub treasury_amount: u64,
+    pub timestamp: i64,
+}
+
+/// Token accounts touched when bonded stake is slashed. The destinations are
+/// only required when they receive something, so agents that never staked can
+/// still be expired or ruled against without them.
+pub struct SlashAccounts<'a, 'info> {
+    pub stake_account: &'a AccountInfo<'info>,
+    pub creator_token_account: Option<&'a Account<'info, TokenAccount>>,
+    pub treasury_token_account: Option<&'a Account<'info, TokenAccount>>,
+    pub token_program: &'a AccountInfo<'info>,
+}
+
+/// Removes `state.slash_bps` of the agent's bond, scaled by `scale_bps`, and
+/// pays it to the creator with `state.slash_treasury_bps` of it going to the treasury.
+pub fn slash_agent_stake<'info>(
+    state: &State,
+    agent: &mut Account<'info, Agent>,
+    task: &Task,
+    scale_bps: u64,
+    accounts: SlashAccounts<'_, 'info>,
+    reason: SlashReason,
+) -> Result<()> {
+    let amount = (agent.bonded_stake as u128)
+        .checked_mul(state.slash_bps as u128)
+        .unwrap()
+        .checked_mul(scale_bps as u128)
+        .unwrap()
+        .checked_div((BPS_DENOMINATOR * BPS_DENOMINATOR) as u128)
+        .unwrap() as u64;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let treasury_amount = (amount as u128)
+        .checked_mul(state.slash_treasury_bps as u128)
+        .unwrap()
+        .checked_div(BPS_DENOMINATOR as u128)
+        .unwrap() as u64;
+    let creator_amount = amount.checked_sub(treasury_amount).unwrap();
+
+    // Destinations are matched against the vault rather than `state.stake_mint`
+    // so stake bonded before a mint change can still be slashed.
+    let vault = Account::<TokenAccount>::try_from(accounts.stake_account)?;
+
+    let id_bytes = agent.id.to_le_bytes();
+    let bump = [agent.bump];
+    let seeds: &[&[u8]] = &[Agent::SEED, &id_bytes, &bump];
+
+    for (destination, share) in [
+        (accounts.creator_token_account, creator_amount),
+        (accounts.treasury_token_account, treasury_amount),
+    ] {
+        if share > 0 {
+            let destination = destination.ok_or(CustomError::InvalidSlashDestination)?;
+            require!(destination.mint == vault.mint, CustomError::InvalidSlashDestination);
+            token::transfer(
+                CpiContext::new_with_signer(
+                    accounts.token_program.clone(),
+                    token::Transfer {
+                        from: accounts.stake_account.clone(),
+                        to: destination.to_account_info(),
+                        authority: agent.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                share,
+            )?;
+        }
+    }
+
+    agent.bonded_stake = agent.bonded_stake.checked_sub(amount).unwrap();
+
+    emit!(SlashEvent {
+        agent_id: agent.id,
+        task_id: task.id,
+        reason,
+        amount,
+        creator_amount,
+        treasury_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -1,35 +1,136 @@
 use anchor_lang::prelude::*;
+use crate::attestation::HashKind;
+use crate::errors::CustomError;
 
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_AGENT_NAME_LEN: usize = 64;
+pub const MAX_AGENT_DESCRIPTION_LEN: usize = 256;
+pub const MAX_METADATA_URI_LEN: usize = 128;
+pub const MAX_TASK_DESCRIPTION_LEN: usize = 256;
+pub const MAX_RESULT_URI_LEN: usize = 128;
+pub const MAX_REJECTION_URI_LEN: usize = 128;
+
+// Agent capabilities and task requirements are bitmasks of these flags.
+pub const CAPABILITY_TEXT: u64 = 1 << 0;
+pub const CAPABILITY_VISION: u64 = 1 << 1;
+pub const CAPABILITY_CODE: u64 = 1 << 2;
+pub const CAPABILITY_TRADING: u64 = 1 << 3;
+pub const CAPABILITY_DATA: u64 = 1 << 4;
+pub const ALL_CAPABILITIES: u64 =
+    CAPABILITY_TEXT | CAPABILITY_VISION | CAPABILITY_CODE | CAPABILITY_TRADING | CAPABILITY_DATA;
+
+// Byte offsets (after the discriminator, id and owner/creator) for memcmp
+// filters on `Agent::capabilities` and `Task::required_capabilities`.
+pub const AGENT_CAPABILITIES_OFFSET: usize = 8 + 8 + 32;
+pub const TASK_REQUIRED_CAPABILITIES_OFFSET: usize = 8 + 8 + 32;
+
+/// Protocol-wide configuration and counters, held in a single PDA at `State::SEED`.
 #[account]
 pub struct State {
     pub authority: Pubkey,
     pub agent_count: u64,
     pub task_count: u64,
     pub is_paused: bool,
+    pub arbiter: Pubkey,
+    pub dispute_window: i64,
+    pub rate_limit_max_actions: u32,
+    pub rate_limit_window: i64,
+    pub stake_mint: Pubkey,
+    pub min_agent_stake: u64,
+    // Additional bond required per unit of task reward, in basis points
+    pub stake_reward_bps: u16,
+    pub slash_bps: u16,
+    pub slash_treasury_bps: u16,
+    // Owner of the token account that receives the treasury's share of slashes
+    pub treasury: Pubkey,
+    // Seconds a creator has to review a submitted result before anyone may finalize it
+    pub review_window: i64,
+    pub recurring_count: u64,
+    pub bump: u8,
 }
 
 #[account]
 pub struct Agent {
     pub id: u64,
     pub owner: Pubkey,
+    // Kept ahead of the strings so it sits at AGENT_CAPABILITIES_OFFSET
+    pub capabilities: u64,
     pub name: String,
     pub description: String,
     pub metadata_uri: String,
     pub reputation_score: u32,
     pub tasks_completed: u32,
     pub is_active: bool,
+    pub operator: Option<Pubkey>,
+    pub bump: u8,
+    pub pending_owner: Option<Pubkey>,
+    // Tasks assigned to the agent whose reward has not been settled yet
+    pub open_tasks: u32,
+    pub bonded_stake: u64,
+    pub tasks_expired: u32,
+    pub disputes_lost: u32,
+    pub total_earned: u64,
+    pub rating_sum: u32,
+    pub rating_count: u32,
+    pub reputation_updated_at: i64,
+    // Ed25519 key whose signature over a result hash marks the result as attested
+    pub attestation_key: Option<Pubkey>,
+    // Locked while a marketplace listing is selling or renting out the agent
+    pub is_listed: bool,
+    // Set while a renter holds the operator key; 0 when not rented
+    pub rented_until: i64,
+    pub tasks_cancelled: u32,
 }
 
 #[account]
 pub struct Task {
     pub id: u64,
     pub creator: Pubkey,
+    // Kept ahead of variable-length fields so it sits at TASK_REQUIRED_CAPABILITIES_OFFSET
+    pub required_capabilities: u64,
     pub agent_id: Option<u64>,
     pub description: String,
     pub reward: u64,
     pub deadline: i64,
     pub status: TaskStatus,
     pub result_uri: Option<String>,
+    pub completed_at: i64,
+    pub delegate: Option<Pubkey>,
+    pub bump: u8,
+    // Creator rating from MIN_RATING to MAX_RATING, 0 while unrated
+    pub rating: u8,
+    // Number of milestones in the task's MilestonePlan, 0 for a single payout
+    pub milestone_count: u8,
+    pub submitted_at: i64,
+    pub rejection_uri: Option<String>,
+    pub rejected_at: i64,
+    // Number of agents in the task's TaskTeam, 0 for a single agent
+    pub team_size: u8,
+    // Only agents on the task's TaskAllowlist may be assigned or bid
+    pub is_private: bool,
+    // Hash of the content at result_uri, checked with attestation::verify_result
+    pub result_hash: [u8; 32],
+    pub result_hash_kind: HashKind,
+    pub result_attested: bool,
+}
+
+/// Holds a task's reward in lamports until it is paid out or refunded.
+#[account]
+pub struct TaskEscrow {
+    pub task: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+/// Per-signer action counter for the sliding-window rate limit.
+#[account]
+pub struct RateLimit {
+    pub signer: Pubkey,
+    pub window_start: i64,
+    pub current_count: u32,
+    pub previous_count: u32,
+    pub bump: u8,
 }
 
 #[account]
@@ -45,18 +146,246 @@ pub enum TaskStatus {
     InProgress,
     Completed,
     Cancelled,
+    Disputed,
+    Resolved,
+    Expired,
+    PartiallyCompleted,
+    Submitted,
+    Rejected,
 }
 
 impl State {
-    pub const SPACE: usize = 8 + 32 + 8 + 8 + 1 + 64;
+    pub const SEED: &'static [u8] = b"state";
+    pub const SPACE: usize = 8 + 32 + 8 + 8 + 1 + 32 + 8 + 4 + 8 + 32 + 8 + 2 + 2 + 2 + 32 + 8 + 8 + 1 + 64;
+
+    /// Stake an agent must have bonded before it can take a task with `reward`.
+    pub fn required_stake(&self, reward: u64) -> u64 {
+        let scaled = (reward as u128)
+            .checked_mul(self.stake_reward_bps as u128)
+            .unwrap()
+            .checked_div(BPS_DENOMINATOR as u128)
+            .unwrap() as u64;
+        self.min_agent_stake.saturating_add(scaled)
+    }
 }
 
 impl Agent {
-    pub const SPACE: usize = 8 + 8 + 32 + 64 + 256 + 128 + 4 + 4 + 1 + 64;
+    pub const SEED: &'static [u8] = b"agent";
+    pub const STAKE_SEED: &'static [u8] = b"agent_stake";
+    pub const SPACE: usize = 8 + // discriminator
+                            8 + // id
+                            32 + // owner
+                            8 + // capabilities
+                            4 + MAX_AGENT_NAME_LEN + // name
+                            4 + MAX_AGENT_DESCRIPTION_LEN + // description
+                            4 + MAX_METADATA_URI_LEN + // metadata_uri
+                            4 + // reputation_score
+                            4 + // tasks_completed
+                            1 + // is_active
+                            33 + // operator
+                            1 + // bump
+                            33 + // pending_owner
+                            4 + // open_tasks
+                            8 + // bonded_stake
+                            4 + // tasks_expired
+                            4 + // disputes_lost
+                            8 + // total_earned
+                            4 + // rating_sum
+                            4 + // rating_count
+                            8 + // reputation_updated_at
+                            33 + // attestation_key
+                            1 + // is_listed
+                            8 + // rented_until
+                            4 + // tasks_cancelled
+                            64; // padding
+
+    pub fn validate_name(name: &str) -> Result<()> {
+        require!(name.len() <= MAX_AGENT_NAME_LEN, CustomError::NameTooLong);
+        Ok(())
+    }
+
+    pub fn validate_description(description: &str) -> Result<()> {
+        require!(description.len() <= MAX_AGENT_DESCRIPTION_LEN, CustomError::DescriptionTooLong);
+        Ok(())
+    }
+
+    pub fn validate_metadata_uri(metadata_uri: &str) -> Result<()> {
+        require!(metadata_uri.len() <= MAX_METADATA_URI_LEN, CustomError::MetadataUriTooLong);
+        Ok(())
+    }
+
+    pub fn validate_capabilities(capabilities: u64) -> Result<()> {
+        require!(capabilities & !ALL_CAPABILITIES == 0, CustomError::InvalidCapabilities);
+        Ok(())
+    }
+
+    /// Whether the agent declares every capability in `required`.
+    pub fn has_capabilities(&self, required: u64) -> bool {
+        self.capabilities & required == required
+    }
+
+    /// Whether `key` may act for this agent: its owner or registered operator.
+    /// A renter's operator key stops counting once the lease runs out, even
+    /// before anyone calls `expire_rental`.
+    pub fn is_operator(&self, key: Pubkey) -> bool {
+        if key == self.owner {
+            return true;
+        }
+        let lease_over = self.rented_until != 0
+            && Clock::get().map_or(true, |clock| self.rented_until <= clock.unix_timestamp);
+        self.operator == Some(key) && !lease_over
+    }
+
+    /// Whether a marketplace listing keeps the agent from taking new tasks. A
+    /// rented agent stays available to its renter until the lease runs out.
+    pub fn is_locked_by_listing(&self, now: i64) -> bool {
+        self.is_listed && self.rented_until <= now
+    }
 }
 
 impl Task {
-    pub const SPACE: usize = 8 + 8 + 32 + 9 + 256 + 8 + 8 + 1 + 129 + 64;
+    pub const SEED: &'static [u8] = b"task";
+    pub const SPACE: usize = 8 + // discriminator
+                            8 + // id
+                            32 + // creator
+                            8 + // required_capabilities
+                            9 + // agent_id
+                            4 + MAX_TASK_DESCRIPTION_LEN + // description
+                            8 + // reward
+                            8 + // deadline
+                            1 + // status
+                            1 + 4 + MAX_RESULT_URI_LEN + // result_uri
+                            8 + // completed_at
+                            33 + // delegate
+                            1 + // bump
+                            1 + // rating
+                            1 + // milestone_count
+                            8 + // submitted_at
+                            1 + 4 + MAX_REJECTION_URI_LEN + // rejection_uri
+                            8 + // rejected_at
+                            1 + // team_size
+                            1 + // is_private
+                            32 + // result_hash
+                            1 + // result_hash_kind
+                            1 + // result_attested
+                            64; // padding
+
+    /// Whether the reward is paid out per milestone rather than on completion.
+    pub fn has_milestones(&self) -> bool {
+        self.milestone_count > 0
+    }
+
+    /// Sets up a freshly created pending task.
+    pub fn initialize(
+        &mut self,
+        id: u64,
+        creator: Pubkey,
+        description: String,
+        reward: u64,
+        deadline: i64,
+        bump: u8,
+    ) {
+        self.id = id;
+        self.creator = creator;
+        self.description = description;
+        self.reward = reward;
+        self.deadline = deadline;
+        self.status = TaskStatus::Pending;
+        self.delegate = None;
+        self.bump = bump;
+        self.required_capabilities = 0;
+        self.rating = 0;
+        self.milestone_count = 0;
+        self.submitted_at = 0;
+        self.rejection_uri = None;
+        self.rejected_at = 0;
+        self.team_size = 0;
+        self.is_private = false;
+        self.result_hash = [0; 32];
+        self.result_hash_kind = HashKind::Sha256;
+        self.result_attested = false;
+    }
+
+    /// Whether the task is shared by a team of agents.
+    pub fn has_team(&self) -> bool {
+        self.team_size > 0
+    }
+
+    /// Whether the task has reached a state it can no longer leave.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TaskStatus::Completed | TaskStatus::Cancelled | TaskStatus::Resolved | TaskStatus::Expired
+        )
+    }
+
+    /// Whether `key` may manage this task: its creator or delegate.
+    pub fn is_manager(&self, key: Pubkey) -> bool {
+        key == self.creator || self.delegate == Some(key)
+    }
+
+    /// Checks that `agent` may take this pending task and assigns it.
+    pub fn assign(&mut self, agent: &mut Agent, state: &State) -> Result<()> {
+        require!(agent.is_active, CustomError::AgentNotActive);
+        require!(
+            !agent.is_locked_by_listing(Clock::get()?.unix_timestamp),
+            CustomError::AgentListed
+        );
+        require!(self.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+        require!(
+            agent.has_capabilities(self.required_capabilities),
+            CustomError::MissingCapabilities
+        );
+        require!(
+            agent.bonded_stake >= state.required_stake(self.reward),
+            CustomError::InsufficientStake
+        );
+
+        self.agent_id = Some(agent.id);
+        self.status = TaskStatus::InProgress;
+        agent.open_tasks = agent.open_tasks.checked_add(1).unwrap();
+        Ok(())
+    }
+}
+
+impl TaskEscrow {
+    pub const SEED: &'static [u8] = b"escrow";
+    pub const SPACE: usize = 8 + 32 + 32 + 8 + 1 + 64;
+}
+
+impl RateLimit {
+    pub const SEED: &'static [u8] = b"rate_limit";
+    pub const SPACE: usize = 8 + 32 + 8 + 4 + 4 + 1 + 64;
+
+    /// Records one action, weighting the previous window's count by how much
+    /// of it still overlaps the sliding window ending at `now`.
+    pub fn record_action(&mut self, now: i64, window: i64, max_actions: u32) -> Result<()> {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed >= window.saturating_mul(2) {
+            self.previous_count = 0;
+            self.current_count = 0;
+            self.window_start = now;
+        } else if elapsed >= window {
+            self.previous_count = self.current_count;
+            self.current_count = 0;
+            self.window_start = self.window_start.checked_add(window).unwrap();
+        }
+
+        let remaining = window.checked_sub(now.saturating_sub(self.window_start)).unwrap();
+        let weighted_previous = (self.previous_count as u64)
+            .checked_mul(remaining as u64)
+            .unwrap()
+            .checked_div(window as u64)
+            .unwrap();
+
+        require!(
+            weighted_previous.checked_add(self.current_count as u64).unwrap() < max_actions as u64,
+            CustomError::RateLimitExceeded
+        );
+
+        self.current_count = self.current_count.checked_add(1).unwrap();
+        Ok(())
+    }
 }
 
 impl Transaction {
@@ -0,0 +1,436 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use crate::allowlist::*;
+use crate::attestation::*;
+use crate::check_rate_limit;
+use crate::errors::CustomError;
+use crate::reputation::*;
+use crate::state::*;
+use crate::transfer_lamports;
+
+pub const MIN_TEAM_MEMBERS: usize = 2;
+pub const MAX_TEAM_MEMBERS: usize = 5;
+
+/// The agents sharing a team task. The first member is the lead and is
+/// recorded as the task's `agent_id`; it answers for disputes and slashing.
+#[account]
+pub struct TaskTeam {
+    pub task: Pubkey,
+    pub members: Vec<TeamMember>,
+    // When set the lead's submission completes the task on behalf of the team
+    pub lead_completes: bool,
+    pub bump: u8,
+    // Paid the team's rent; gets it back when the team is closed
+    pub payer: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TeamMember {
+    pub agent_id: u64,
+    pub share_bps: u16,
+    pub result_uri: Option<String>,
+    pub result_hash: [u8; 32],
+    pub result_hash_kind: HashKind,
+    // 0 until the member submits; a submission older than a rejection no longer counts
+    pub submitted_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TeamShare {
+    pub agent_id: u64,
+    pub share_bps: u16,
+}
+
+#[derive(Accounts)]
+pub struct AssignTeam<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        constraint = task.is_manager(authority.key()) @ CustomError::NotTaskManager
+    )]
+    pub task: Account<'info, Task>,
+    /// CHECK: the task's allowlist; only read when the task is private.
+    #[account(seeds = [TaskAllowlist::SEED, task.key().as_ref()], bump)]
+    pub allowlist: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TaskTeam::SPACE,
+        seeds = [TaskTeam::SEED, task.key().as_ref()],
+        bump
+    )]
+    pub team: Account<'info, TaskTeam>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + RateLimit::SPACE,
+        seeds = [RateLimit::SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitTeamResult<'info> {
+    #[account(
+        seeds = [State::SEED],
+        bump = state.bump,
+        constraint = !state.is_paused @ CustomError::ProtocolPaused
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub task: Account<'info, Task>,
+    #[account(constraint = agent.is_operator(authority.key()) @ CustomError::NotAgentOperator)]
+    pub agent: Account<'info, Agent>,
+    #[account(
+        mut,
+        seeds = [TaskTeam::SEED, task.key().as_ref()],
+        bump = team.bump
+    )]
+    pub team: Account<'info, TaskTeam>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + RateLimit::SPACE,
+        seeds = [RateLimit::SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: the instructions sysvar, read for the ed25519 attestation.
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTaskTeam<'info> {
+    pub task: Account<'info, Task>,
+    #[account(
+        mut,
+        seeds = [TaskTeam::SEED, task.key().as_ref()],
+        bump = team.bump,
+        close = payer
+    )]
+    pub team: Account<'info, TaskTeam>,
+    /// CHECK: receives the team rent; address is pinned to whoever assigned the team.
+    #[account(mut, address = team.payer @ CustomError::Unauthorized)]
+    pub payer: UncheckedAccount<'info>,
+}
+
+impl TaskTeam {
+    pub const SEED: &'static [u8] = b"team";
+    pub const SPACE: usize = 8 + // discriminator
+                            32 + // task
+                            4 + (MAX_TEAM_MEMBERS * TeamMember::SPACE) + // members
+                            1 + // lead_completes
+                            1 + // bump
+                            32 + // payer
+                            64; // padding
+}
+
+impl TeamMember {
+    pub const SPACE: usize = 8 + // agent_id
+                            2 + // share_bps
+                            1 + 4 + MAX_RESULT_URI_LEN + // result_uri
+                            32 + // result_hash
+                            1 + // result_hash_kind
+                            8; // submitted_at
+}
+
+#[event]
+pub struct TeamAssigned {
+    pub task_id: u64,
+    pub agent_ids: Vec<u64>,
+    pub lead_completes: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TeamResultSubmitted {
+    pub task_id: u64,
+    pub agent_id: u64,
+    // Set when this submission moved the task to review
+    pub task_submitted: bool,
+    pub timestamp: i64,
+}
+
+/// Assigns a pending task to a team. The member agents are passed as writable
+/// remaining accounts in the same order as `members`; the first is the lead.
+/// For a private task `proofs` holds each member's allowlist proof, in order.
+pub fn assign_team<'info>(
+    ctx: Context<'_, '_, '_, 'info, AssignTeam<'info>>,
+    members: Vec<TeamShare>,
+    lead_completes: bool,
+    proofs: Vec<Vec<[u8; 32]>>,
+) -> Result<()> {
+    check_rate_limit(
+        &mut ctx.accounts.rate_limit,
+        ctx.accounts.authority.key(),
+        *ctx.bumps.get("rate_limit").unwrap(),
+        &ctx.accounts.state,
+    )?;
+
+    let state = &ctx.accounts.state;
+    let task = &mut ctx.accounts.task;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(task.status == TaskStatus::Pending, CustomError::InvalidTaskStatus);
+    require!(!task.has_milestones(), CustomError::TaskHasMilestones);
+    require!(
+        (MIN_TEAM_MEMBERS..=MAX_TEAM_MEMBERS).contains(&members.len()),
+        CustomError::InvalidTeam
+    );
+    require!(ctx.remaining_accounts.len() == members.len(), CustomError::InvalidTeam);
+
+    let mut total_bps: u64 = 0;
+    for (i, (member, info)) in members.iter().zip(ctx.remaining_accounts.iter()).enumerate() {
+        require!(member.share_bps > 0, CustomError::InvalidShare);
+        require!(
+            members[..i].iter().all(|m| m.agent_id != member.agent_id),
+            CustomError::InvalidTeam
+        );
+        total_bps = total_bps.checked_add(member.share_bps as u64).unwrap();
+
+        let mut agent = Account::<Agent>::try_from(info)?;
+        require!(agent.id == member.agent_id, CustomError::AgentIdMismatch);
+        require!(agent.is_active, CustomError::AgentNotActive);
+        require!(!agent.is_locked_by_listing(now), CustomError::AgentListed);
+        require!(
+            agent.has_capabilities(task.required_capabilities),
+            CustomError::MissingCapabilities
+        );
+        check_allowlist(
+            task,
+            &ctx.accounts.allowlist,
+            &agent,
+            proofs.get(i).map_or(&[][..], |p| &p[..]),
+        )?;
+        require!(
+            agent.bonded_stake >= state.required_stake(share_of(task.reward, member.share_bps)),
+            CustomError::InsufficientStake
+        );
+
+        agent.open_tasks = agent.open_tasks.checked_add(1).unwrap();
+        agent.exit(&crate::ID)?;
+    }
+    require!(total_bps == BPS_DENOMINATOR, CustomError::InvalidShare);
+
+    let team = &mut ctx.accounts.team;
+    team.task = task.key();
+    team.members = members
+        .iter()
+        .map(|m| TeamMember {
+            agent_id: m.agent_id,
+            share_bps: m.share_bps,
+            result_uri: None,
+            result_hash: [0; 32],
+            result_hash_kind: HashKind::Sha256,
+            submitted_at: 0,
+        })
+        .collect();
+    team.lead_completes = lead_completes;
+    team.bump = *ctx.bumps.get("team").unwrap();
+    team.payer = ctx.accounts.authority.key();
+
+    task.agent_id = Some(members[0].agent_id);
+    task.team_size = members.len() as u8;
+    task.status = TaskStatus::InProgress;
+
+    emit!(TeamAssigned {
+        task_id: task.id,
+        agent_ids: members.iter().map(|m| m.agent_id).collect(),
+        lead_completes,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Records a member's result. The task moves to review once every member has
+/// submitted, or once the lead has when the team is lead-completed. The
+/// lead's result and hash become the task's.
+pub fn submit_team_result(
+    ctx: Context<SubmitTeamResult>,
+    result_uri: String,
+    result_hash: [u8; 32],
+    hash_kind: HashKind,
+    attested: bool,
+) -> Result<()> {
+    require!(result_uri.len() <= MAX_RESULT_URI_LEN, CustomError::ResultUriTooLong);
+
+    check_rate_limit(
+        &mut ctx.accounts.rate_limit,
+        ctx.accounts.authority.key(),
+        *ctx.bumps.get("rate_limit").unwrap(),
+        &ctx.accounts.state,
+    )?;
+
+    let task = &mut ctx.accounts.task;
+    let team = &mut ctx.accounts.team;
+    let agent = &ctx.accounts.agent;
+    let agent_id = agent.id;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        task.status == TaskStatus::InProgress || task.status == TaskStatus::Rejected,
+        CustomError::InvalidTaskStatus
+    );
+    require!(now <= task.deadline, CustomError::TaskDeadlinePassed);
+
+    let member = team
+        .members
+        .iter_mut()
+        .find(|m| m.agent_id == agent_id)
+        .ok_or(CustomError::AgentTaskMismatch)?;
+    member.result_uri = Some(result_uri.clone());
+    member.result_hash = result_hash;
+    member.result_hash_kind = hash_kind;
+    member.submitted_at = now;
+
+    let is_lead = task.agent_id == Some(agent_id);
+    if is_lead {
+        task.result_uri = Some(result_uri);
+        commit_result(task, agent, result_hash, hash_kind, attested, &ctx.accounts.instructions)?;
+    } else if attested {
+        check_attestation(&ctx.accounts.instructions, agent, &task.key(), &result_hash)?;
+    }
+
+    let rejected_at = task.rejected_at;
+    let ready = if team.lead_completes {
+        is_lead
+    } else {
+        team.members.iter().all(|m| m.submitted_at > rejected_at)
+    };
+    if ready {
+        task.status = TaskStatus::Submitted;
+        task.submitted_at = now;
+    }
+
+    emit!(TeamResultSubmitted {
+        task_id: task.id,
+        agent_id,
+        task_submitted: ready,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Closes the team of a finished task and returns its rent to whoever
+/// assigned it. Anyone may call it.
+pub fn close_task_team(ctx: Context<CloseTaskTeam>) -> Result<()> {
+    require!(ctx.accounts.task.is_finished(), CustomError::InvalidTaskStatus);
+    Ok(())
+}
+
+fn share_of(amount: u64, share_bps: u16) -> u64 {
+    (amount as u128)
+        .checked_mul(share_bps as u128)
+        .unwrap()
+        .checked_div(BPS_DENOMINATOR as u128)
+        .unwrap() as u64
+}
+
+/// A non-lead member loaded from the remaining accounts.
+struct MemberAccounts<'info> {
+    agent: Account<'info, Agent>,
+    owner: Option<AccountInfo<'info>>,
+    share_bps: u16,
+}
+
+/// Loads the team of `task` and its non-lead members from the remaining
+/// accounts: the team PDA, then each member agent in team order, each followed
+/// by its owner when `with_owners` is set.
+fn load_members<'info>(
+    task_key: Pubkey,
+    remaining: &[AccountInfo<'info>],
+    with_owners: bool,
+) -> Result<Vec<MemberAccounts<'info>>> {
+    let (team_info, rest) = remaining.split_first().ok_or(CustomError::InvalidTeam)?;
+    let team = Account::<TaskTeam>::try_from(team_info)?;
+    require!(team.task == task_key, CustomError::InvalidTeam);
+
+    let stride = if with_owners { 2 } else { 1 };
+    let members = &team.members[1..];
+    require!(rest.len() == members.len() * stride, CustomError::InvalidTeam);
+
+    members
+        .iter()
+        .zip(rest.chunks(stride))
+        .map(|(member, infos)| {
+            let agent = Account::<Agent>::try_from(&infos[0])?;
+            require!(agent.id == member.agent_id, CustomError::AgentIdMismatch);
+            let owner = if with_owners {
+                require!(infos[1].key() == agent.owner, CustomError::Unauthorized);
+                Some(infos[1].clone())
+            } else {
+                None
+            };
+            Ok(MemberAccounts {
+                agent,
+                owner,
+                share_bps: member.share_bps,
+            })
+        })
+        .collect()
+}
+
+/// Pays each non-lead member its share of `amount` from the escrow, credits
+/// it and releases it from the task. Returns the total paid; the remainder,
+/// including rounding dust, is the lead's.
+pub fn pay_team_members<'info>(
+    task_key: Pubkey,
+    escrow: &AccountInfo<'info>,
+    amount: u64,
+    remaining: &[AccountInfo<'info>],
+    now: i64,
+) -> Result<u64> {
+    let mut paid: u64 = 0;
+    for MemberAccounts { mut agent, owner, share_bps } in load_members(task_key, remaining, true)? {
+        let share = share_of(amount, share_bps);
+        if share > 0 {
+            transfer_lamports(escrow, &owner.unwrap(), share)?;
+            agent.tasks_completed = agent.tasks_completed.checked_add(1).unwrap();
+            apply_outcome(&mut agent, ReputationOutcome::Completed { reward: share }, now)?;
+            paid = paid.checked_add(share).unwrap();
+        }
+        agent.open_tasks = agent.open_tasks.saturating_sub(1);
+        agent.exit(&crate::ID)?;
+    }
+    Ok(paid)
+}
+
+/// Releases the non-lead members of a team task the lead gave back. Only the
+/// lead chose to cancel, so the members' reputation is left alone.
+pub fn release_team_members<'info>(task_key: Pubkey, remaining: &[AccountInfo<'info>]) -> Result<()> {
+    for MemberAccounts { mut agent, .. } in load_members(task_key, remaining, false)? {
+        agent.open_tasks = agent.open_tasks.saturating_sub(1);
+        agent.exit(&crate::ID)?;
+    }
+    Ok(())
+}
+
+/// Releases the non-lead members of an expired team task, recording the expiry
+/// against each of them.
+pub fn expire_team_members<'info>(
+    task_key: Pubkey,
+    remaining: &[AccountInfo<'info>],
+    now: i64,
+) -> Result<()> {
+    for MemberAccounts { mut agent, .. } in load_members(task_key, remaining, false)? {
+        agent.open_tasks = agent.open_tasks.saturating_sub(1);
+        apply_outcome(&mut agent, ReputationOutcome::Expired, now)?;
+        agent.exit(&crate::ID)?;
+    }
+    Ok(())
+}
@@ -5,10 +5,14 @@ import {
   TOKEN_PROGRAM_ID,
   createInitializeMintInstruction,
   createAssociatedTokenAccountInstruction,
+  createAssociatedTokenAccountIdempotentInstruction,
   getAssociatedTokenAddressSync,
   createMintToInstruction,
+  getAccount,
   MINT_SIZE,
 } from "@solana/spl-token";
+import { sha256 } from "@noble/hashes/sha256";
+import { keccak_256 } from "@noble/hashes/sha3";
 import { assert } from "chai";
 
 describe("solana-ai-nexus", () => {
@@ -17,24 +21,398 @@ describe("solana-ai-nexus", () => {
 
   const program = anchor.workspace.SolanaAiNexus as Program<SolanaAiNexus>;
   
-  let state: anchor.web3.Keypair;
   let mint: anchor.web3.Keypair;
   let userTokenAccount: anchor.web3.PublicKey;
   let stakeAccount: anchor.web3.PublicKey;
+
+  const findCounterPda = (seed: string, id: anchor.BN) =>
+    anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(seed), id.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
   
+  type TestAgent = {
+    agent: anchor.web3.PublicKey;
+    agentId: anchor.BN;
+    owner: anchor.web3.Keypair;
+  };
+
+  type TestTask = {
+    task: anchor.web3.PublicKey;
+    escrow: anchor.web3.PublicKey;
+    deadline: number;
+    creator: anchor.web3.Keypair;
+  };
+
+  const wallet = provider.wallet.publicKey;
+  const systemProgram = anchor.web3.SystemProgram.programId;
+  const instructions = anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY;
+  const arbiter = anchor.web3.Keypair.generate();
+  const resultHash = Array.from(Buffer.alloc(32, 7));
+
+  const pda = (...seeds: Buffer[]) =>
+    anchor.web3.PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const state = pda(Buffer.from("state"));
+  const escrowPda = (task: anchor.web3.PublicKey) => pda(Buffer.from("escrow"), task.toBuffer());
+  const rateLimitPda = (signer: anchor.web3.PublicKey) =>
+    pda(Buffer.from("rate_limit"), signer.toBuffer());
+  const allowlistPda = (task: anchor.web3.PublicKey) =>
+    pda(Buffer.from("allowlist"), task.toBuffer());
+  const stakePda = (agent: anchor.web3.PublicKey) =>
+    pda(Buffer.from("agent_stake"), agent.toBuffer());
+  const disputePda = (task: anchor.web3.PublicKey) =>
+    pda(Buffer.from("dispute"), task.toBuffer());
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  // Deadlines and review windows are checked against the validator clock,
+  // which can drift from the host's.
+  const chainTime = async () => {
+    const clock = await provider.connection.getAccountInfo(anchor.web3.SYSVAR_CLOCK_PUBKEY);
+    return new anchor.BN(clock.data.subarray(32, 40), "le").toNumber();
+  };
+
+  const waitPast = async (timestamp: number) => {
+    while ((await chainTime()) <= timestamp) {
+      await sleep(500);
+    }
+  };
+
+  const balance = (key: anchor.web3.PublicKey) => provider.connection.getBalance(key);
+
+  const expectError = async (action: Promise<unknown>, code: string) => {
+    try {
+      await action;
+    } catch (error) {
+      assert.include(error.toString(), code);
+      return;
+    }
+    assert.fail(`expected the instruction to fail with ${code}`);
+  };
+
+  const fundedKeypair = async (sol = 2) => {
+    const keypair = anchor.web3.Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(
+      keypair.publicKey,
+      sol * anchor.web3.LAMPORTS_PER_SOL
+    );
+    const latest = await provider.connection.getLatestBlockhash();
+    await provider.connection.confirmTransaction({ signature, ...latest });
+    return keypair;
+  };
+
+  const registerAgent = async (owner: anchor.web3.Keypair, capabilities = 1): Promise<TestAgent> => {
+    const { agentCount } = await program.account.state.fetch(state);
+    const agent = findCounterPda("agent", agentCount);
+    await program.methods
+      .registerAgent("Agent", "Test agent", "https://agent.uri", new anchor.BN(capabilities))
+      .accounts({ state, agent, owner: owner.publicKey, systemProgram })
+      .signers([owner])
+      .rpc();
+    return { agent, agentId: agentCount, owner };
+  };
+
+  const createTask = async (
+    creator: anchor.web3.Keypair,
+    reward: number,
+    duration = 3600,
+    capabilities = 0
+  ): Promise<TestTask> => {
+    const { taskCount } = await program.account.state.fetch(state);
+    const task = findCounterPda("task", taskCount);
+    const deadline = (await chainTime()) + duration;
+    await program.methods
+      .createTask("Test task", new anchor.BN(reward), new anchor.BN(deadline), new anchor.BN(capabilities))
+      .accounts({
+        state,
+        task,
+        escrow: escrowPda(task),
+        rateLimit: rateLimitPda(creator.publicKey),
+        creator: creator.publicKey,
+        systemProgram,
+      })
+      .signers([creator])
+      .rpc();
+    return { task, escrow: escrowPda(task), deadline, creator };
+  };
+
+  const assignTask = (
+    task: TestTask,
+    agent: TestAgent,
+    manager: anchor.web3.Keypair = task.creator,
+    proof: number[][] = []
+  ) =>
+    program.methods
+      .assignTask(agent.agentId, proof)
+      .accounts({
+        state,
+        task: task.task,
+        agent: agent.agent,
+        allowlist: allowlistPda(task.task),
+        rateLimit: rateLimitPda(manager.publicKey),
+        authority: manager.publicKey,
+        systemProgram,
+      })
+      .signers([manager])
+      .rpc();
+
+  const completeTask = (
+    task: TestTask,
+    agent: TestAgent,
+    operator: anchor.web3.Keypair = agent.owner,
+    resultUri = "https://result.uri"
+  ) =>
+    program.methods
+      .completeTask(resultUri, resultHash, { sha256: {} }, false)
+      .accounts({
+        state,
+        task: task.task,
+        agent: agent.agent,
+        rateLimit: rateLimitPda(operator.publicKey),
+        authority: operator.publicKey,
+        systemProgram,
+        instructions,
+      })
+      .signers([operator])
+      .rpc();
+
+  const rejectResult = (task: TestTask, reasonUri = "https://rejection.uri") =>
+    program.methods
+      .rejectResult(reasonUri)
+      .accounts({ state, task: task.task, creator: task.creator.publicKey })
+      .signers([task.creator])
+      .rpc();
+
+  const approveResult = (task: TestTask, agent: TestAgent, remaining: anchor.web3.AccountMeta[] = []) =>
+    program.methods
+      .approveResult()
+      .accounts({
+        task: task.task,
+        agent: agent.agent,
+        escrow: task.escrow,
+        agentOwner: agent.owner.publicKey,
+        creator: task.creator.publicKey,
+      })
+      .remainingAccounts(remaining)
+      .signers([task.creator])
+      .rpc();
+
+  // Assigns, submits and rejects a result so a dispute can be opened on it.
+  const deliverRejected = async (task: TestTask, agent: TestAgent) => {
+    await assignTask(task, agent);
+    await completeTask(task, agent);
+    await rejectResult(task);
+  };
+
+  const openDispute = (task: TestTask, agent: TestAgent, opener: anchor.web3.Keypair) =>
+    program.methods
+      .openDispute("https://dispute.uri")
+      .accounts({
+        state,
+        task: task.task,
+        agent: agent.agent,
+        dispute: disputePda(task.task),
+        opener: opener.publicKey,
+        systemProgram,
+      })
+      .signers([opener])
+      .rpc();
+
+  type SlashAccounts = Partial<{
+    creatorTokenAccount: anchor.web3.PublicKey | null;
+    treasuryTokenAccount: anchor.web3.PublicKey | null;
+  }>;
+
+  const resolveDispute = (
+    task: TestTask,
+    agent: TestAgent,
+    creatorShareBps: number,
+    signer: anchor.web3.Keypair = arbiter,
+    slash: SlashAccounts = {}
+  ) =>
+    program.methods
+      .resolveDispute(creatorShareBps)
+      .accounts({
+        state,
+        task: task.task,
+        agent: agent.agent,
+        dispute: disputePda(task.task),
+        escrow: task.escrow,
+        creator: task.creator.publicKey,
+        agentOwner: agent.owner.publicKey,
+        stakeAccount: stakePda(agent.agent),
+        creatorTokenAccount: null,
+        treasuryTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        arbiter: signer.publicKey,
+        ...slash,
+      })
+      .signers([signer])
+      .rpc();
+
+  type ExpiryAccounts = SlashAccounts &
+    Partial<{
+      plan: anchor.web3.PublicKey | null;
+      agentOwner: anchor.web3.PublicKey | null;
+    }>;
+
+  const expireTask = (task: TestTask, agent: TestAgent, extra: ExpiryAccounts = {}) =>
+    program.methods
+      .expireTask()
+      .accounts({
+        state,
+        task: task.task,
+        agent: agent.agent,
+        escrow: task.escrow,
+        creator: task.creator.publicKey,
+        stakeAccount: stakePda(agent.agent),
+        creatorTokenAccount: null,
+        treasuryTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        plan: null,
+        agentOwner: null,
+        ...extra,
+      })
+      .rpc();
+
+  const tokenBalance = async (account: anchor.web3.PublicKey) =>
+    Number((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+  // Creates `owner`'s token account for the test mint and mints `amount` into it.
+  const fundTokens = async (owner: anchor.web3.PublicKey, amount: number) => {
+    const account = getAssociatedTokenAddressSync(mint.publicKey, owner, false, TOKEN_PROGRAM_ID);
+    const tx = new anchor.web3.Transaction().add(
+      createAssociatedTokenAccountIdempotentInstruction(
+        wallet,
+        account,
+        owner,
+        mint.publicKey,
+        TOKEN_PROGRAM_ID
+      )
+    );
+    if (amount > 0) {
+      tx.add(createMintToInstruction(mint.publicKey, account, wallet, amount, [], TOKEN_PROGRAM_ID));
+    }
+    await provider.sendAndConfirm(tx);
+    return account;
+  };
+
+  const stakeAgent = async (agent: TestAgent, amount: number) => {
+    const ownerTokenAccount = await fundTokens(agent.owner.publicKey, amount);
+    await program.methods
+      .stakeTokens(new anchor.BN(amount))
+      .accounts({
+        state,
+        agent: agent.agent,
+        staker: agent.owner.publicKey,
+        userTokenAccount: ownerTokenAccount,
+        stakeAccount: stakePda(agent.agent),
+        stakeMint: mint.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram,
+      })
+      .signers([agent.owner])
+      .rpc();
+  };
+
+  const setSlashConfig = (slashBps: number, treasuryBps: number) =>
+    program.methods
+      .setSlashConfig(slashBps, treasuryBps, wallet)
+      .accounts({ state, authority: wallet })
+      .rpc();
+
+  const planPda = (task: anchor.web3.PublicKey) => pda(Buffer.from("milestones"), task.toBuffer());
+
+  const setRateLimit = (maxActions: number, window: number) =>
+    program.methods
+      .setRateLimit(maxActions, new anchor.BN(window))
+      .accounts({ state, authority: wallet })
+      .rpc();
+
+  const setReviewWindow = (seconds: number) =>
+    program.methods
+      .setReviewWindow(new anchor.BN(seconds))
+      .accounts({ state, authority: wallet })
+      .rpc();
+
+  const marketplace = pda(Buffer.from("marketplace"));
+  const marketplaceTreasury = pda(Buffer.from("marketplace_treasury"));
+
+  // The marketplace is a singleton PDA, so it is set up once for every suite that trades agents.
+  const ensureMarketplace = async () => {
+    if (await provider.connection.getAccountInfo(marketplace)) {
+      return;
+    }
+    await program.methods
+      .initializeMarketplace()
+      .accounts({
+        state,
+        marketplace,
+        paymentMint: mint.publicKey,
+        treasury: marketplaceTreasury,
+        authority: wallet,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram,
+      })
+      .rpc();
+  };
+
+  const nextListingPda = async () => {
+    const { listingCount } = await program.account.marketplace.fetch(marketplace);
+    return findCounterPda("listing", listingCount);
+  };
+
+  const createListing = async (agent: TestAgent, price: number) => {
+    const listing = await nextListingPda();
+    await program.methods
+      .createListing(agent.agentId, new anchor.BN(price), "Agent for sale")
+      .accounts({
+        state,
+        marketplace,
+        listing,
+        agent: agent.agent,
+        seller: agent.owner.publicKey,
+        systemProgram,
+      })
+      .signers([agent.owner])
+      .rpc();
+    return listing;
+  };
+
+  const purchaseListing = async (
+    listing: anchor.web3.PublicKey,
+    agent: TestAgent,
+    buyer: anchor.web3.Keypair
+  ) => {
+    const { seller } = await program.account.agentListing.fetch(listing);
+    return program.methods
+      .purchaseListing()
+      .accounts({
+        state,
+        marketplace,
+        listing,
+        agent: agent.agent,
+        buyer: buyer.publicKey,
+        buyerTokenAccount: await fundTokens(buyer.publicKey, 0),
+        sellerTokenAccount: await fundTokens(seller, 0),
+        treasury: marketplaceTreasury,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+  };
+
   before(async () => {
-    state = anchor.web3.Keypair.generate();
     mint = anchor.web3.Keypair.generate();
 
     try {
       await program.methods
         .initialize()
         .accounts({
-          state: state.publicKey,
+          state,
           authority: provider.wallet.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
-        .signers([state])
         .rpc();
 
       const lamports = await provider.connection.getMinimumBalanceForRentExemption(MINT_SIZE);
@@ -85,88 +463,2270 @@ describe("solana-ai-nexus", () => {
         .add(mintToIx);
 
       await provider.sendAndConfirm(tx, [mint]);
-
-      stakeAccount = getAssociatedTokenAddressSync(
-        mint.publicKey,
-        provider.wallet.publicKey,
-        true,
-        TOKEN_PROGRAM_ID
-      );
-
     } catch (error) {
       console.error("Error in test setup:", error);
       throw error;
     }
   });
 
+  before(async () => {
+    // The suites below act many times per signer within a minute.
+    await setRateLimit(1000, 60);
+    await program.methods
+      .setDisputeConfig(arbiter.publicKey, new anchor.BN(3600))
+      .accounts({ state, authority: wallet })
+      .rpc();
+  });
+
   it("Initializes the program state", async () => {
-    const stateAccount = await program.account.state.fetch(state.publicKey);
+    const stateAccount = await program.account.state.fetch(state);
     assert.equal(stateAccount.authority.toString(), provider.wallet.publicKey.toString());
     assert.equal(stateAccount.agentCount, 0);
     assert.equal(stateAccount.taskCount, 0);
+
+    // The state lives at a single PDA, so it cannot be created twice.
+    const intruder = await fundedKeypair();
+    await expectError(
+      program.methods
+        .initialize()
+        .accounts({ state, authority: intruder.publicKey, systemProgram })
+        .signers([intruder])
+        .rpc(),
+      "0x0"
+    );
   });
 
   it("Registers an agent", async () => {
-    const agent = anchor.web3.Keypair.generate();
+    const agent = findCounterPda("agent", new anchor.BN(0));
     const name = "Test Agent";
     const description = "Test Description";
     const metadataUri = "https://test.uri";
+    const capabilities = new anchor.BN(1); // text
 
     await program.methods
-      .registerAgent(name, description, metadataUri)
+      .registerAgent(name, description, metadataUri, capabilities)
       .accounts({
-        state: state.publicKey,
-        agent: agent.publicKey,
+        state,
+        agent,
         owner: provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
-      .signers([agent])
       .rpc();
 
-    const agentAccount = await program.account.agent.fetch(agent.publicKey);
+    const agentAccount = await program.account.agent.fetch(agent);
     assert.equal(agentAccount.name, name);
     assert.equal(agentAccount.description, description);
     assert.equal(agentAccount.metadataUri, metadataUri);
     assert.equal(agentAccount.reputationScore, 0);
     assert.equal(agentAccount.tasksCompleted, 0);
     assert.equal(agentAccount.isActive, true);
+    assert.equal(agentAccount.capabilities.toNumber(), 1);
+
+    const stateAccount = await program.account.state.fetch(state);
+    assert.equal(stateAccount.agentCount.toNumber(), 1);
+  });
+
+  it("Updates an agent profile", async () => {
+    const agent = findCounterPda("agent", new anchor.BN(0));
+    const metadataUri = "https://test.uri/v2";
+
+    await program.methods
+      .updateAgent(null, null, metadataUri, null)
+      .accounts({
+        agent,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    await program.methods
+      .setAgentActive(false)
+      .accounts({
+        agent,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const agentAccount = await program.account.agent.fetch(agent);
+    assert.equal(agentAccount.name, "Test Agent");
+    assert.equal(agentAccount.metadataUri, metadataUri);
+    assert.equal(agentAccount.isActive, false);
   });
 
   it("Creates and manages a task", async () => {
-    const task = anchor.web3.Keypair.generate();
+    const task = findCounterPda("task", new anchor.BN(0));
     const description = "Test Task";
     const reward = new anchor.BN(100);
     const deadline = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+    const [escrow] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), task.toBuffer()],
+      program.programId
+    );
+    const [rateLimit] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("rate_limit"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
 
     await program.methods
-      .createTask(description, reward, deadline)
+      .createTask(description, reward, deadline, new anchor.BN(0))
       .accounts({
-        state: state.publicKey,
-        task: task.publicKey,
+        state,
+        task,
+        escrow,
+        rateLimit,
         creator: provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
-      .signers([task])
       .rpc();
 
-    const taskAccount = await program.account.task.fetch(task.publicKey);
+    const taskAccount = await program.account.task.fetch(task);
     assert.equal(taskAccount.description, description);
     assert.equal(taskAccount.reward.toString(), reward.toString());
     assert.equal(taskAccount.deadline.toString(), deadline.toString());
     assert.deepEqual(taskAccount.status, { pending: {} });
+
+    const rateLimitAccount = await program.account.rateLimit.fetch(rateLimit);
+    assert.equal(rateLimitAccount.currentCount, 1);
+
+    const escrowAccount = await program.account.taskEscrow.fetch(escrow);
+    assert.equal(escrowAccount.task.toString(), task.toString());
+    assert.equal(escrowAccount.amount.toString(), reward.toString());
+
+    await program.methods
+      .cancelTask()
+      .accounts({
+        task,
+        escrow,
+        creator: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const cancelledTask = await program.account.task.fetch(task);
+    assert.deepEqual(cancelledTask.status, { cancelled: {} });
+    assert.isNull(await provider.connection.getAccountInfo(escrow));
   });
 
   it("Stakes tokens", async () => {
     const amount = new anchor.BN(1000000);
+    const agent = findCounterPda("agent", new anchor.BN(0));
+    [stakeAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("agent_stake"), agent.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .setStakeConfig(mint.publicKey, new anchor.BN(0), 0)
+      .accounts({
+        state,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
 
     await program.methods
       .stakeTokens(amount)
       .accounts({
+        state,
+        agent,
         staker: provider.wallet.publicKey,
         userTokenAccount: userTokenAccount,
         stakeAccount: stakeAccount,
+        stakeMint: mint.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const agentAccount = await program.account.agent.fetch(agent);
+    assert.equal(agentAccount.bondedStake.toString(), amount.toString());
+  });
+
+  it("Rejects mutating instructions while paused", async () => {
+    await program.methods
+      .pauseProtocol()
+      .accounts({
+        state,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    const agent = findCounterPda("agent", new anchor.BN(1));
+    try {
+      await program.methods
+        .registerAgent("Paused Agent", "Paused", "https://paused.uri", new anchor.BN(0))
+        .accounts({
+          state,
+          agent,
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("register_agent should fail while paused");
+    } catch (error) {
+      assert.include(error.toString(), "ProtocolPaused");
+    }
+
+    await program.methods
+      .unpauseProtocol()
+      .accounts({
+        state,
+        authority: provider.wallet.publicKey,
       })
       .rpc();
+
+    const stateAccount = await program.account.state.fetch(state);
+    assert.equal(stateAccount.isPaused, false);
+  });
+
+  it("Rejects an agent name longer than the maximum", async () => {
+    const agent = findCounterPda("agent", new anchor.BN(1));
+
+    try {
+      await program.methods
+        .registerAgent("a".repeat(65), "Too long", "https://long.uri", new anchor.BN(0))
+        .accounts({
+          state,
+          agent,
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("register_agent should reject the name");
+    } catch (error) {
+      assert.include(error.toString(), "NameTooLong");
+    }
+  });
+
+  it("Rejects descriptions and URIs longer than their maximums", async () => {
+    const owner = await fundedKeypair();
+    const { agentCount, taskCount } = await program.account.state.fetch(state);
+    const agent = findCounterPda("agent", agentCount);
+    const task = findCounterPda("task", taskCount);
+    const longUri = "https://" + "a".repeat(121);
+
+    const register = (description: string, metadataUri: string) =>
+      program.methods
+        .registerAgent("Agent", description, metadataUri, new anchor.BN(1))
+        .accounts({ state, agent, owner: owner.publicKey, systemProgram })
+        .signers([owner])
+        .rpc();
+    await expectError(register("a".repeat(257), "https://agent.uri"), "DescriptionTooLong");
+    await expectError(register("Test agent", longUri), "MetadataUriTooLong");
+
+    const deadline = new anchor.BN((await chainTime()) + 3600);
+    await expectError(
+      program.methods
+        .createTask("a".repeat(257), new anchor.BN(1_000_000), deadline, new anchor.BN(0))
+        .accounts({
+          state,
+          task,
+          escrow: escrowPda(task),
+          rateLimit: rateLimitPda(owner.publicKey),
+          creator: owner.publicKey,
+          systemProgram,
+        })
+        .signers([owner])
+        .rpc(),
+      "DescriptionTooLong"
+    );
+
+    const worker = await registerAgent(owner);
+    const created = await createTask(owner, 1_000_000);
+    await assignTask(created, worker);
+    await expectError(completeTask(created, worker, owner, longUri), "ResultUriTooLong");
+    await completeTask(created, worker);
+    await expectError(rejectResult(created, longUri), "RejectionUriTooLong");
+  });
+
+  describe("disputes", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+
+    const closeDispute = (task: TestTask, openedBy: anchor.web3.PublicKey) =>
+      program.methods
+        .closeDispute()
+        .accounts({ dispute: disputePda(task.task), openedBy })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    it("Only lets a party open a dispute on a rejected result", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await completeTask(task, worker);
+      await expectError(openDispute(task, worker, worker.owner), "InvalidTaskStatus");
+
+      await rejectResult(task);
+      await expectError(openDispute(task, worker, outsider), "Unauthorized");
+
+      await openDispute(task, worker, creator);
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { disputed: {} });
+      const dispute = await program.account.dispute.fetch(disputePda(task.task));
+      assert.equal(dispute.openedBy.toString(), creator.publicKey.toString());
+      assert.deepEqual(dispute.status, { open: {} });
+    });
+
+    it("Resolves a dispute by splitting the escrow and closes it", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await deliverRejected(task, worker);
+      await openDispute(task, worker, worker.owner);
+
+      await program.methods
+        .submitEvidence("https://evidence.uri", Array.from(Buffer.alloc(32, 1)))
+        .accounts({
+          task: task.task,
+          agent: worker.agent,
+          dispute: disputePda(task.task),
+          submitter: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+      await expectError(
+        program.methods
+          .submitEvidence("https://evidence.uri", Array.from(Buffer.alloc(32, 2)))
+          .accounts({
+            task: task.task,
+            agent: worker.agent,
+            dispute: disputePda(task.task),
+            submitter: outsider.publicKey,
+          })
+          .signers([outsider])
+          .rpc(),
+        "Unauthorized"
+      );
+
+      await expectError(closeDispute(task, worker.owner.publicKey), "InvalidDisputeStatus");
+      await expectError(resolveDispute(task, worker, 2500, outsider), "Unauthorized");
+
+      const ownerBefore = await balance(worker.owner.publicKey);
+      await resolveDispute(task, worker, 2500);
+      assert.equal((await balance(worker.owner.publicKey)) - ownerBefore, 750_000);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { resolved: {} });
+      const dispute = await program.account.dispute.fetch(disputePda(task.task));
+      assert.deepEqual(dispute.status, { resolved: {} });
+      assert.equal(dispute.creatorShareBps, 2500);
+      assert.equal(dispute.evidence.length, 1);
+      assert.isNull(await provider.connection.getAccountInfo(task.escrow));
+
+      await expectError(resolveDispute(task, worker, 0), "InvalidDisputeStatus");
+      await expectError(closeDispute(task, outsider.publicKey), "Unauthorized");
+
+      const openerBefore = await balance(worker.owner.publicKey);
+      await closeDispute(task, worker.owner.publicKey);
+      assert.isNull(await provider.connection.getAccountInfo(disputePda(task.task)));
+      assert.isAbove(await balance(worker.owner.publicKey), openerBefore);
+    });
+  });
+
+  describe("rate limiting", () => {
+    let creator: anchor.web3.Keypair;
+
+    before(async () => {
+      creator = await fundedKeypair();
+      await setRateLimit(2, 4);
+    });
+
+    after(async () => {
+      await setRateLimit(1000, 60);
+    });
+
+    it("Rejects actions over the limit until the window rolls over", async () => {
+      await createTask(creator, 1_000_000);
+      await createTask(creator, 1_000_000);
+      await expectError(createTask(creator, 1_000_000), "RateLimitExceeded");
+
+      // Two full windows after the first action both counts have lapsed.
+      await waitPast((await chainTime()) + 8);
+      await createTask(creator, 1_000_000);
+
+      const rateLimit = await program.account.rateLimit.fetch(rateLimitPda(creator.publicKey));
+      assert.equal(rateLimit.currentCount, 1);
+      assert.equal(rateLimit.previousCount, 0);
+    });
+  });
+
+  describe("task access control", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+    let other: TestAgent;
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+      other = await registerAgent(await fundedKeypair());
+    });
+
+    it("Only lets the creator or its delegate assign a task", async () => {
+      const task = await createTask(creator, 1_000_000);
+
+      await expectError(assignTask(task, worker, outsider), "NotTaskManager");
+      await expectError(assignTask(task, { ...worker, agentId: other.agentId }), "AgentIdMismatch");
+      await expectError(
+        program.methods
+          .setTaskDelegate(outsider.publicKey)
+          .accounts({ task: task.task, creator: outsider.publicKey })
+          .signers([outsider])
+          .rpc(),
+        "Unauthorized"
+      );
+
+      await program.methods
+        .setTaskDelegate(outsider.publicKey)
+        .accounts({ task: task.task, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await assignTask(task, worker, outsider);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { inProgress: {} });
+      assert.equal(taskAccount.agentId.toString(), worker.agentId.toString());
+    });
+
+    it("Only lets the assigned agent's owner or operator complete a task", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+
+      await expectError(completeTask(task, worker, outsider), "NotAgentOperator");
+      await expectError(completeTask(task, other), "AgentTaskMismatch");
+
+      await program.methods
+        .setAgentOperator(outsider.publicKey)
+        .accounts({ agent: worker.agent, owner: worker.owner.publicKey })
+        .signers([worker.owner])
+        .rpc();
+      await completeTask(task, worker, outsider);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+
+      await program.methods
+        .setAgentOperator(null)
+        .accounts({ agent: worker.agent, owner: worker.owner.publicKey })
+        .signers([worker.owner])
+        .rpc();
+    });
+  });
+
+  describe("cancellation and expiry", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+
+    const cancelTask = (task: TestTask, signer: anchor.web3.Keypair) =>
+      program.methods
+        .cancelTask()
+        .accounts({ task: task.task, escrow: task.escrow, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    it("Only lets the creator cancel a pending task", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await expectError(cancelTask(task, outsider), "Unauthorized");
+
+      await assignTask(task, worker);
+      await expectError(cancelTask(task, creator), "InvalidTaskStatus");
+
+      const pending = await createTask(creator, 1_000_000);
+      await cancelTask(pending, creator);
+      const taskAccount = await program.account.task.fetch(pending.task);
+      assert.deepEqual(taskAccount.status, { cancelled: {} });
+      assert.isNull(await provider.connection.getAccountInfo(pending.escrow));
+    });
+
+    it("Expires a task once its deadline has passed and refunds the creator", async () => {
+      const late = await registerAgent(await fundedKeypair());
+      const task = await createTask(creator, 1_000_000, 3);
+      await assignTask(task, late);
+      await expectError(expireTask(task, late), "TaskDeadlineNotReached");
+
+      await waitPast(task.deadline);
+      await expectError(completeTask(task, late), "TaskDeadlinePassed");
+
+      const creatorBefore = await balance(creator.publicKey);
+      const escrowLamports = await balance(task.escrow);
+      await expireTask(task, late);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, escrowLamports);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { expired: {} });
+      const agentAccount = await program.account.agent.fetch(late.agent);
+      assert.equal(agentAccount.tasksExpired, 1);
+      assert.equal(agentAccount.openTasks, 0);
+
+      await expectError(expireTask(task, late), "AccountNotInitialized");
+    });
+  });
+
+  describe("stake bonding", () => {
+    let creator: anchor.web3.Keypair;
+
+    const setStakeConfig = (minStake: number, rewardBps: number) =>
+      program.methods
+        .setStakeConfig(mint.publicKey, new anchor.BN(minStake), rewardBps)
+        .accounts({ state, authority: wallet })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      // A flat 1000 plus a tenth of the task reward.
+      await setStakeConfig(1000, 1000);
+    });
+
+    after(async () => {
+      await setStakeConfig(0, 0);
+    });
+
+    it("Only assigns agents that have bonded the required stake", async () => {
+      const worker = await registerAgent(await fundedKeypair());
+      const task = await createTask(creator, 1_000_000);
+
+      await expectError(assignTask(task, worker), "InsufficientStake");
+      await stakeAgent(worker, 100_000);
+      await expectError(assignTask(task, worker), "InsufficientStake");
+      await stakeAgent(worker, 1000);
+      await assignTask(task, worker);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { inProgress: {} });
+    });
+
+    it("Refuses to close an agent until its stake is withdrawn", async () => {
+      const agent = await registerAgent(await fundedKeypair());
+      await stakeAgent(agent, 5000);
+
+      const closeAgent = () =>
+        program.methods
+          .closeAgent()
+          .accounts({ agent: agent.agent, owner: agent.owner.publicKey })
+          .signers([agent.owner])
+          .rpc();
+      await expectError(closeAgent(), "StakeStillBonded");
+
+      await program.methods
+        .unstakeTokens(new anchor.BN(5000))
+        .accounts({
+          agent: agent.agent,
+          staker: agent.owner.publicKey,
+          userTokenAccount: await fundTokens(agent.owner.publicKey, 0),
+          stakeAccount: stakePda(agent.agent),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([agent.owner])
+        .rpc();
+      await closeAgent();
+      assert.isNull(await provider.connection.getAccountInfo(agent.agent));
+    });
+  });
+
+  describe("slashing", () => {
+    let creator: anchor.web3.Keypair;
+    let creatorTokenAccount: anchor.web3.PublicKey;
+
+    before(async () => {
+      creator = await fundedKeypair();
+      creatorTokenAccount = await fundTokens(creator.publicKey, 0);
+      // Half of the bond per full slash, a fifth of it to the treasury.
+      await setSlashConfig(5000, 2000);
+    });
+
+    after(async () => {
+      await setSlashConfig(0, 0);
+    });
+
+    it("Expires an unstaked agent's task without token accounts", async () => {
+      const worker = await registerAgent(await fundedKeypair());
+      const task = await createTask(creator, 1_000_000, 3);
+      await assignTask(task, worker);
+      await waitPast(task.deadline);
+
+      await expireTask(task, worker);
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.bondedStake.toNumber(), 0);
+      assert.equal(agentAccount.tasksExpired, 1);
+    });
+
+    it("Slashes bonded stake to the creator and treasury on expiry", async () => {
+      const worker = await registerAgent(await fundedKeypair());
+      await stakeAgent(worker, 1_000_000);
+      const task = await createTask(creator, 1_000_000, 3);
+      await assignTask(task, worker);
+      await waitPast(task.deadline);
+
+      await expectError(expireTask(task, worker), "InvalidSlashDestination");
+      await expectError(
+        expireTask(task, worker, {
+          creatorTokenAccount: userTokenAccount,
+          treasuryTokenAccount: userTokenAccount,
+        }),
+        "InvalidSlashDestination"
+      );
+
+      const creatorBefore = await tokenBalance(creatorTokenAccount);
+      const treasuryBefore = await tokenBalance(userTokenAccount);
+      await expireTask(task, worker, { creatorTokenAccount, treasuryTokenAccount: userTokenAccount });
+
+      assert.equal((await tokenBalance(creatorTokenAccount)) - creatorBefore, 400_000);
+      assert.equal((await tokenBalance(userTokenAccount)) - treasuryBefore, 100_000);
+      assert.equal(await tokenBalance(stakePda(worker.agent)), 500_000);
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.bondedStake.toNumber(), 500_000);
+    });
+
+    it("Resolves a dispute against an unstaked agent without token accounts", async () => {
+      const worker = await registerAgent(await fundedKeypair());
+      const task = await createTask(creator, 1_000_000);
+      await deliverRejected(task, worker);
+      await openDispute(task, worker, creator);
+
+      const ownerBefore = await balance(worker.owner.publicKey);
+      await resolveDispute(task, worker, 10000);
+      assert.equal(await balance(worker.owner.publicKey), ownerBefore);
+
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.disputesLost, 1);
+      assert.equal(agentAccount.bondedStake.toNumber(), 0);
+    });
+
+    it("Slashes bonded stake in proportion to a dispute ruling", async () => {
+      const worker = await registerAgent(await fundedKeypair());
+      await stakeAgent(worker, 1_000_000);
+      const task = await createTask(creator, 1_000_000);
+      await deliverRejected(task, worker);
+      await openDispute(task, worker, creator);
+
+      await expectError(resolveDispute(task, worker, 5000), "InvalidSlashDestination");
+
+      const creatorBefore = await tokenBalance(creatorTokenAccount);
+      const treasuryBefore = await tokenBalance(userTokenAccount);
+      await resolveDispute(task, worker, 5000, arbiter, {
+        creatorTokenAccount,
+        treasuryTokenAccount: userTokenAccount,
+      });
+
+      // 50% of the slash rate on a 50% ruling: 250_000 of the bond.
+      assert.equal((await tokenBalance(creatorTokenAccount)) - creatorBefore, 200_000);
+      assert.equal((await tokenBalance(userTokenAccount)) - treasuryBefore, 50_000);
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.bondedStake.toNumber(), 750_000);
+    });
+  });
+
+  describe("reputation", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+
+    const rateTask = (task: TestTask, agent: TestAgent, rating: number, signer = task.creator) =>
+      program.methods
+        .rateTask(rating)
+        .accounts({ task: task.task, agent: agent.agent, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const cancelAssignedTask = (task: TestTask, agent: TestAgent, signer: anchor.web3.Keypair) =>
+      program.methods
+        .cancelAssignedTask()
+        .accounts({
+          task: task.task,
+          agent: agent.agent,
+          escrow: task.escrow,
+          creator: task.creator.publicKey,
+          authority: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    it("Derives reputation from settled tasks and creator ratings", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await expectError(rateTask(task, worker, 5), "InvalidTaskStatus");
+
+      await completeTask(task, worker);
+      await approveResult(task, worker);
+      let agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.reputationScore, 10);
+      assert.equal(agentAccount.tasksCompleted, 1);
+
+      await expectError(rateTask(task, worker, 0), "InvalidRating");
+      await expectError(rateTask(task, worker, 6), "InvalidRating");
+      await expectError(rateTask(task, worker, 5, outsider), "Unauthorized");
+
+      await rateTask(task, worker, 5);
+      await expectError(rateTask(task, worker, 1), "TaskAlreadyRated");
+
+      agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.reputationScore, 14);
+      assert.equal(agentAccount.ratingCount, 1);
+      assert.equal(agentAccount.ratingSum, 5);
+    });
+
+    it("Penalizes an agent that gives an assigned task back", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+
+      await expectError(cancelAssignedTask(task, worker, outsider), "NotAgentOperator");
+
+      const { reputationScore } = await program.account.agent.fetch(worker.agent);
+      const creatorBefore = await balance(creator.publicKey);
+      const escrowLamports = await balance(task.escrow);
+      await cancelAssignedTask(task, worker, worker.owner);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, escrowLamports);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { cancelled: {} });
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.tasksCancelled, 1);
+      assert.equal(agentAccount.openTasks, 0);
+      assert.equal(agentAccount.reputationScore, reputationScore - 3);
+    });
+
+    it("Only lets the protocol authority override a score", async () => {
+      const overrideScore = (signer: anchor.web3.Keypair | null, delta: number, reason: string) => {
+        const builder = program.methods
+          .updateReputation(worker.agentId, delta, reason)
+          .accounts({
+            state,
+            agent: worker.agent,
+            authority: signer ? signer.publicKey : wallet,
+          });
+        return signer ? builder.signers([signer]).rpc() : builder.rpc();
+      };
+
+      await expectError(overrideScore(outsider, 5, "Manual correction"), "Unauthorized");
+      await expectError(overrideScore(null, 5, ""), "InvalidOverrideReason");
+
+      const { reputationScore } = await program.account.agent.fetch(worker.agent);
+      await overrideScore(null, 5, "Manual correction");
+      const agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.reputationScore, reputationScore + 5);
+    });
+  });
+
+  describe("bidding", () => {
+    let creator: anchor.web3.Keypair;
+    let agents: TestAgent[];
+
+    const bidPda = (task: TestTask, agent: TestAgent) =>
+      pda(Buffer.from("bid"), task.task.toBuffer(), agent.agent.toBuffer());
+
+    const submitBid = (task: TestTask, agent: TestAgent, price: number) =>
+      program.methods
+        .submitBid(new anchor.BN(price), new anchor.BN(3600), "https://proposal.uri", [])
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          allowlist: allowlistPda(task.task),
+          bid: bidPda(task, agent),
+          bidder: agent.owner.publicKey,
+          systemProgram,
+        })
+        .signers([agent.owner])
+        .rpc();
+
+    const withdrawBid = (task: TestTask, agent: TestAgent, bidder: anchor.web3.Keypair) =>
+      program.methods
+        .withdrawBid()
+        .accounts({ task: task.task, bid: bidPda(task, agent), bidder: bidder.publicKey })
+        .signers([bidder])
+        .rpc();
+
+    const closeBid = (task: TestTask, agent: TestAgent) =>
+      program.methods
+        .closeBid()
+        .accounts({ task: task.task, bid: bidPda(task, agent), bidder: agent.owner.publicKey })
+        .rpc();
+
+    const acceptBid = (task: TestTask, agent: TestAgent, manager: anchor.web3.Keypair = task.creator) =>
+      program.methods
+        .acceptBid([])
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          allowlist: allowlistPda(task.task),
+          bid: bidPda(task, agent),
+          bidder: agent.owner.publicKey,
+          escrow: task.escrow,
+          creator: task.creator.publicKey,
+          authority: manager.publicKey,
+        })
+        .signers([manager])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      agents = [];
+      for (let i = 0; i < 3; i++) {
+        agents.push(await registerAgent(await fundedKeypair()));
+      }
+    });
+
+    it("Collects bids and lets bidders withdraw them while the task is open", async () => {
+      const task = await createTask(creator, 1_000_000);
+      const [first, second] = agents;
+
+      await expectError(submitBid(task, first, 2_000_000), "InvalidBidPrice");
+      await submitBid(task, first, 800_000);
+      await submitBid(task, second, 600_000);
+
+      const bid = await program.account.bid.fetch(bidPda(task, second));
+      assert.equal(bid.price.toNumber(), 600_000);
+      assert.equal(bid.agentId.toString(), second.agentId.toString());
+
+      await expectError(withdrawBid(task, first, second.owner), "Unauthorized");
+      await expectError(closeBid(task, first), "InvalidTaskStatus");
+
+      await withdrawBid(task, first, first.owner);
+      assert.isNull(await provider.connection.getAccountInfo(bidPda(task, first)));
+    });
+
+    it("Accepts a bid at its price and closes losing bids afterwards", async () => {
+      const task = await createTask(creator, 1_000_000);
+      const [, second, third] = agents;
+      await submitBid(task, second, 600_000);
+      await submitBid(task, third, 700_000);
+
+      await expectError(acceptBid(task, second, third.owner), "NotTaskManager");
+
+      const creatorBefore = await balance(creator.publicKey);
+      await acceptBid(task, second);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, 400_000);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { inProgress: {} });
+      assert.equal(taskAccount.reward.toNumber(), 600_000);
+      assert.equal(taskAccount.agentId.toString(), second.agentId.toString());
+      const escrowAccount = await program.account.taskEscrow.fetch(task.escrow);
+      assert.equal(escrowAccount.amount.toNumber(), 600_000);
+      assert.isNull(await provider.connection.getAccountInfo(bidPda(task, second)));
+
+      await expectError(withdrawBid(task, third, third.owner), "InvalidTaskStatus");
+      const bidderBefore = await balance(third.owner.publicKey);
+      await closeBid(task, third);
+      assert.isNull(await provider.connection.getAccountInfo(bidPda(task, third)));
+      assert.isAbove(await balance(third.owner.publicKey), bidderBefore);
+    });
+  });
+
+  describe("milestones", () => {
+    let creator: anchor.web3.Keypair;
+    let worker: TestAgent;
+
+    const addMilestones = (
+      task: TestTask,
+      milestones: { amount: number; deadline: number }[],
+      signer: anchor.web3.Keypair = task.creator
+    ) =>
+      program.methods
+        .addMilestones(
+          milestones.map((m) => ({ amount: new anchor.BN(m.amount), deadline: new anchor.BN(m.deadline) }))
+        )
+        .accounts({ task: task.task, plan: planPda(task.task), creator: signer.publicKey, systemProgram })
+        .signers([signer])
+        .rpc();
+
+    const submitMilestone = (task: TestTask, agent: TestAgent, index: number) =>
+      program.methods
+        .submitMilestone(index, "https://milestone.uri")
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          plan: planPda(task.task),
+          authority: agent.owner.publicKey,
+        })
+        .signers([agent.owner])
+        .rpc();
+
+    const approveMilestone = (task: TestTask, agent: TestAgent, index: number) =>
+      program.methods
+        .approveMilestone(index)
+        .accounts({
+          task: task.task,
+          agent: agent.agent,
+          plan: planPda(task.task),
+          escrow: task.escrow,
+          agentOwner: agent.owner.publicKey,
+          creator: task.creator.publicKey,
+        })
+        .signers([task.creator])
+        .rpc();
+
+    const finalizeMilestone = (task: TestTask, agent: TestAgent, index: number) =>
+      program.methods
+        .finalizeMilestone(index)
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          plan: planPda(task.task),
+          escrow: task.escrow,
+          agentOwner: agent.owner.publicKey,
+          creator: task.creator.publicKey,
+        })
+        .rpc();
+
+    const closeMilestonePlan = (task: TestTask) =>
+      program.methods
+        .closeMilestonePlan()
+        .accounts({ task: task.task, plan: planPda(task.task), creator: task.creator.publicKey })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    after(async () => {
+      await setReviewWindow(3 * 24 * 60 * 60);
+    });
+
+    it("Pays each milestone on approval or once its review window lapses", async () => {
+      const task = await createTask(creator, 1_000_000);
+      const halfway = task.deadline - 1800;
+
+      await expectError(
+        addMilestones(task, [
+          { amount: 400_000, deadline: halfway },
+          { amount: 500_000, deadline: task.deadline },
+        ]),
+        "InvalidMilestones"
+      );
+      await expectError(
+        addMilestones(task, [
+          { amount: 400_000, deadline: task.deadline },
+          { amount: 600_000, deadline: halfway },
+        ]),
+        "InvalidMilestones"
+      );
+      await addMilestones(task, [
+        { amount: 400_000, deadline: halfway },
+        { amount: 600_000, deadline: task.deadline },
+      ]);
+
+      await assignTask(task, worker);
+      await expectError(completeTask(task, worker), "TaskHasMilestones");
+
+      await submitMilestone(task, worker, 0);
+      let ownerBefore = await balance(worker.owner.publicKey);
+      await approveMilestone(task, worker, 0);
+      assert.equal((await balance(worker.owner.publicKey)) - ownerBefore, 400_000);
+      let taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { partiallyCompleted: {} });
+
+      await expectError(approveMilestone(task, worker, 1), "InvalidMilestoneStatus");
+      await expectError(closeMilestonePlan(task), "InvalidTaskStatus");
+
+      await submitMilestone(task, worker, 1);
+      await expectError(finalizeMilestone(task, worker, 1), "ReviewWindowOpen");
+
+      await setReviewWindow(1);
+      const plan = await program.account.milestonePlan.fetch(planPda(task.task));
+      await waitPast(plan.milestones[1].submittedAt.toNumber() + 1);
+
+      ownerBefore = await balance(worker.owner.publicKey);
+      await finalizeMilestone(task, worker, 1);
+      assert.equal((await balance(worker.owner.publicKey)) - ownerBefore, 600_000);
+
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { completed: {} });
+      assert.isNull(await provider.connection.getAccountInfo(task.escrow));
+
+      await closeMilestonePlan(task);
+      assert.isNull(await provider.connection.getAccountInfo(planPda(task.task)));
+    });
+
+    it("Pays milestones delivered on time when the task expires", async () => {
+      const late = await registerAgent(await fundedKeypair());
+      const task = await createTask(creator, 1_000_000, 8);
+      await addMilestones(task, [
+        { amount: 300_000, deadline: task.deadline - 4 },
+        { amount: 700_000, deadline: task.deadline },
+      ]);
+      await assignTask(task, late);
+      await submitMilestone(task, late, 0);
+      await waitPast(task.deadline);
+
+      await expectError(expireTask(task, late), "InvalidMilestones");
+
+      const ownerBefore = await balance(late.owner.publicKey);
+      const creatorBefore = await balance(creator.publicKey);
+      const escrowLamports = await balance(task.escrow);
+      await expireTask(task, late, { plan: planPda(task.task), agentOwner: late.owner.publicKey });
+
+      assert.equal((await balance(late.owner.publicKey)) - ownerBefore, 300_000);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, escrowLamports - 300_000);
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { expired: {} });
+      const plan = await program.account.milestonePlan.fetch(planPda(task.task));
+      assert.deepEqual(plan.milestones[0].status, { approved: {} });
+      assert.deepEqual(plan.milestones[1].status, { pending: {} });
+    });
+  });
+
+  describe("result review", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+
+    const finalizeTask = (task: TestTask, agent: TestAgent) =>
+      program.methods
+        .finalizeTask()
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          escrow: task.escrow,
+          creator: task.creator.publicKey,
+          agentOwner: agent.owner.publicKey,
+        })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    after(async () => {
+      await setReviewWindow(3 * 24 * 60 * 60);
+    });
+
+    it("Pays the agent only once the creator approves a submitted result", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await expectError(approveResult(task, worker), "InvalidTaskStatus");
+
+      await completeTask(task, worker);
+      let taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+      assert.deepEqual(taskAccount.resultHash, resultHash);
+
+      await expectError(approveResult({ ...task, creator: outsider }, worker), "Unauthorized");
+
+      const ownerBefore = await balance(worker.owner.publicKey);
+      await approveResult(task, worker);
+      assert.equal((await balance(worker.owner.publicKey)) - ownerBefore, 1_000_000);
+
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { completed: {} });
+      assert.isNull(await provider.connection.getAccountInfo(task.escrow));
+    });
+
+    it("Lets the agent resubmit a rejected result", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await completeTask(task, worker);
+
+      await expectError(rejectResult({ ...task, creator: outsider }), "Unauthorized");
+      await rejectResult(task);
+      let taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { rejected: {} });
+      assert.equal(taskAccount.rejectionUri, "https://rejection.uri");
+      await expectError(approveResult(task, worker), "InvalidTaskStatus");
+
+      await completeTask(task, worker);
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+      await approveResult(task, worker);
+    });
+
+    it("Finalizes a result the creator leaves unreviewed", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await completeTask(task, worker);
+      await expectError(finalizeTask(task, worker), "ReviewWindowOpen");
+
+      await setReviewWindow(1);
+      const { submittedAt } = await program.account.task.fetch(task.task);
+      await waitPast(submittedAt.toNumber() + 1);
+      await expectError(rejectResult(task), "ReviewWindowClosed");
+
+      const ownerBefore = await balance(worker.owner.publicKey);
+      await finalizeTask(task, worker);
+      assert.equal((await balance(worker.owner.publicKey)) - ownerBefore, 1_000_000);
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { completed: {} });
+    });
+  });
+
+  describe("team tasks", () => {
+    let creator: anchor.web3.Keypair;
+    let lead: TestAgent;
+    let second: TestAgent;
+    let third: TestAgent;
+    let outsider: TestAgent;
+
+    const teamPda = (task: TestTask) => pda(Buffer.from("team"), task.task.toBuffer());
+    const writable = (pubkey: anchor.web3.PublicKey) => ({ pubkey, isSigner: false, isWritable: true });
+
+    const assignTeam = (task: TestTask, members: [TestAgent, number][], leadCompletes = false) =>
+      program.methods
+        .assignTeam(
+          members.map(([agent, shareBps]) => ({ agentId: agent.agentId, shareBps })),
+          leadCompletes,
+          []
+        )
+        .accounts({
+          state,
+          task: task.task,
+          allowlist: allowlistPda(task.task),
+          team: teamPda(task),
+          rateLimit: rateLimitPda(task.creator.publicKey),
+          authority: task.creator.publicKey,
+          systemProgram,
+        })
+        .remainingAccounts(members.map(([agent]) => writable(agent.agent)))
+        .signers([task.creator])
+        .rpc();
+
+    const submitTeamResult = (task: TestTask, agent: TestAgent) =>
+      program.methods
+        .submitTeamResult("https://team-result.uri", resultHash, { sha256: {} }, false)
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          team: teamPda(task),
+          rateLimit: rateLimitPda(agent.owner.publicKey),
+          authority: agent.owner.publicKey,
+          systemProgram,
+          instructions,
+        })
+        .signers([agent.owner])
+        .rpc();
+
+    const closeTaskTeam = (task: TestTask) =>
+      program.methods
+        .closeTaskTeam()
+        .accounts({ task: task.task, team: teamPda(task), payer: task.creator.publicKey })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      lead = await registerAgent(await fundedKeypair());
+      second = await registerAgent(await fundedKeypair());
+      third = await registerAgent(await fundedKeypair());
+      outsider = await registerAgent(await fundedKeypair());
+    });
+
+    it("Splits a team task's reward across its members by share", async () => {
+      const task = await createTask(creator, 1_000_000);
+
+      await expectError(assignTeam(task, [[lead, 10000]]), "InvalidTeam");
+      await expectError(
+        assignTeam(task, [
+          [lead, 5000],
+          [second, 3000],
+          [third, 1000],
+        ]),
+        "InvalidShare"
+      );
+      await assignTeam(task, [
+        [lead, 5000],
+        [second, 3000],
+        [third, 2000],
+      ]);
+
+      const team = await program.account.taskTeam.fetch(teamPda(task));
+      assert.equal(team.members.length, 3);
+      assert.equal(team.payer.toString(), creator.publicKey.toString());
+      let taskAccount = await program.account.task.fetch(task.task);
+      assert.equal(taskAccount.agentId.toString(), lead.agentId.toString());
+
+      await expectError(completeTask(task, lead), "TaskHasTeam");
+      await expectError(submitTeamResult(task, outsider), "AgentTaskMismatch");
+
+      await submitTeamResult(task, second);
+      await submitTeamResult(task, lead);
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { inProgress: {} });
+      await submitTeamResult(task, third);
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+
+      await expectError(closeTaskTeam(task), "InvalidTaskStatus");
+
+      const owners = [lead, second, third].map((agent) => agent.owner.publicKey);
+      const balancesBefore = await Promise.all(owners.map(balance));
+      await approveResult(task, lead, [
+        writable(teamPda(task)),
+        writable(second.agent),
+        writable(second.owner.publicKey),
+        writable(third.agent),
+        writable(third.owner.publicKey),
+      ]);
+      const balancesAfter = await Promise.all(owners.map(balance));
+      assert.deepEqual(
+        balancesAfter.map((lamports, i) => lamports - balancesBefore[i]),
+        [500_000, 300_000, 200_000]
+      );
+
+      for (const agent of [lead, second, third]) {
+        const agentAccount = await program.account.agent.fetch(agent.agent);
+        assert.equal(agentAccount.openTasks, 0);
+        assert.equal(agentAccount.tasksCompleted, 1);
+      }
+
+      await closeTaskTeam(task);
+      assert.isNull(await provider.connection.getAccountInfo(teamPda(task)));
+    });
+
+    it("Moves a lead-completed team task to review on the lead's submission", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await assignTeam(
+        task,
+        [
+          [lead, 6000],
+          [second, 4000],
+        ],
+        true
+      );
+
+      await submitTeamResult(task, lead);
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+    });
+  });
+
+  describe("recurring tasks", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+
+    const createRecurringTask = async (interval: number, maxRuns: number) => {
+      const { recurringCount } = await program.account.state.fetch(state);
+      const template = findCounterPda("recurring", recurringCount);
+      await program.methods
+        .createRecurringTask(
+          "Recurring task",
+          new anchor.BN(100_000),
+          new anchor.BN(interval),
+          new anchor.BN(3600),
+          maxRuns,
+          new anchor.BN(0),
+          new anchor.BN(0)
+        )
+        .accounts({ state, template, creator: creator.publicKey, systemProgram })
+        .signers([creator])
+        .rpc();
+      return template;
+    };
+
+    const spawnRecurringTask = async (template: anchor.web3.PublicKey) => {
+      const { taskCount } = await program.account.state.fetch(state);
+      const task = findCounterPda("task", taskCount);
+      await program.methods
+        .spawnRecurringTask()
+        .accounts({
+          state,
+          template,
+          task,
+          escrow: escrowPda(task),
+          cranker: wallet,
+          systemProgram,
+        })
+        .rpc();
+      return task;
+    };
+
+    const setPaused = (template: anchor.web3.PublicKey, paused: boolean, signer = creator) =>
+      program.methods
+        .setRecurringTaskPaused(paused)
+        .accounts({ template, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const cancelRecurringTask = (template: anchor.web3.PublicKey, signer = creator) =>
+      program.methods
+        .cancelRecurringTask()
+        .accounts({ template, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+    });
+
+    it("Spawns funded tasks on schedule until its runs are used up", async () => {
+      await expectError(createRecurringTask(0, 2), "InvalidInterval");
+      await expectError(createRecurringTask(3, 0), "InvalidMaxRuns");
+      const template = await createRecurringTask(3, 2);
+
+      const task = await spawnRecurringTask(template);
+      const taskAccount = await program.account.task.fetch(task);
+      assert.deepEqual(taskAccount.status, { pending: {} });
+      assert.equal(taskAccount.creator.toString(), creator.publicKey.toString());
+      assert.equal(taskAccount.reward.toNumber(), 100_000);
+      const escrowAccount = await program.account.taskEscrow.fetch(escrowPda(task));
+      assert.equal(escrowAccount.amount.toNumber(), 100_000);
+
+      await expectError(spawnRecurringTask(template), "RecurringRunNotDue");
+
+      await expectError(setPaused(template, true, outsider), "Unauthorized");
+      await setPaused(template, true);
+      const { nextRunAt } = await program.account.recurringTask.fetch(template);
+      await waitPast(nextRunAt.toNumber() - 1);
+      await expectError(spawnRecurringTask(template), "RecurringTaskPaused");
+
+      await setPaused(template, false);
+      await spawnRecurringTask(template);
+      const templateAccount = await program.account.recurringTask.fetch(template);
+      assert.equal(templateAccount.runsSpawned, 2);
+
+      await expectError(spawnRecurringTask(template), "RecurringRunsExhausted");
+    });
+
+    it("Refunds the unspent runs when the template is cancelled", async () => {
+      const template = await createRecurringTask(60, 3);
+      await expectError(cancelRecurringTask(template, outsider), "Unauthorized");
+
+      const creatorBefore = await balance(creator.publicKey);
+      const templateLamports = await balance(template);
+      await cancelRecurringTask(template);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, templateLamports);
+      assert.isNull(await provider.connection.getAccountInfo(template));
+    });
+  });
+
+  describe("agent capabilities", () => {
+    // Bit flags matching CAPABILITY_TEXT and CAPABILITY_CODE; 1 << 5 is undefined.
+    const TEXT = 1;
+    const CODE = 1 << 2;
+    const UNKNOWN = 1 << 5;
+    let creator: anchor.web3.Keypair;
+
+    before(async () => {
+      creator = await fundedKeypair();
+    });
+
+    it("Rejects capability bits outside the defined set", async () => {
+      await expectError(registerAgent(await fundedKeypair(), TEXT | UNKNOWN), "InvalidCapabilities");
+      await expectError(createTask(creator, 1_000_000, 3600, UNKNOWN), "InvalidCapabilities");
+    });
+
+    it("Only assigns agents that declare every required capability", async () => {
+      const worker = await registerAgent(await fundedKeypair(), TEXT);
+      const task = await createTask(creator, 1_000_000, 3600, TEXT | CODE);
+
+      await expectError(assignTask(task, worker), "MissingCapabilities");
+
+      await program.methods
+        .updateAgent(null, null, null, new anchor.BN(TEXT | CODE))
+        .accounts({ agent: worker.agent, owner: worker.owner.publicKey })
+        .signers([worker.owner])
+        .rpc();
+      await assignTask(task, worker);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { inProgress: {} });
+    });
+  });
+
+  describe("task allowlists", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let listed: TestAgent;
+    let byOwner: TestAgent;
+    let unlisted: TestAgent;
+
+    const agentEntry = (agent: TestAgent) => ({ agent: { "0": agent.agentId } });
+    const ownerEntry = (agent: TestAgent) => ({ owner: { "0": agent.owner.publicKey } });
+
+    // Leaves and sorted-pair hashing match `AllowlistEntry::leaf` and `verify_proof`.
+    const keccak = (...parts: Buffer[]) => Buffer.from(keccak_256(Buffer.concat(parts)));
+    const hashPair = (a: Buffer, b: Buffer) => (Buffer.compare(a, b) <= 0 ? keccak(a, b) : keccak(b, a));
+    const agentLeaf = (id: anchor.BN) => keccak(Buffer.from("agent"), id.toArrayLike(Buffer, "le", 8));
+    const ownerLeaf = (owner: anchor.web3.PublicKey) => keccak(Buffer.from("owner"), owner.toBuffer());
+
+    const setTaskAllowlist = (
+      task: TestTask,
+      entries: object[],
+      merkleRoot: Buffer | null = null,
+      signer: anchor.web3.Keypair = task.creator
+    ) =>
+      program.methods
+        .setTaskAllowlist(entries, merkleRoot ? Array.from(merkleRoot) : null)
+        .accounts({
+          task: task.task,
+          allowlist: allowlistPda(task.task),
+          authority: signer.publicKey,
+          systemProgram,
+        })
+        .signers([signer])
+        .rpc();
+
+    const removeTaskAllowlist = (task: TestTask, signer: anchor.web3.Keypair = task.creator) =>
+      program.methods
+        .removeTaskAllowlist()
+        .accounts({
+          task: task.task,
+          allowlist: allowlistPda(task.task),
+          creator: task.creator.publicKey,
+          authority: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      listed = await registerAgent(await fundedKeypair());
+      byOwner = await registerAgent(await fundedKeypair());
+      unlisted = await registerAgent(await fundedKeypair());
+    });
+
+    it("Restricts a private task to the agents and owners listed inline", async () => {
+      const task = await createTask(creator, 1_000_000);
+
+      await expectError(setTaskAllowlist(task, [agentEntry(listed)], null, outsider), "NotTaskManager");
+      await expectError(setTaskAllowlist(task, []), "InvalidAllowlist");
+      await setTaskAllowlist(task, [agentEntry(listed), ownerEntry(byOwner)]);
+
+      let taskAccount = await program.account.task.fetch(task.task);
+      assert.isTrue(taskAccount.isPrivate);
+      const allowlist = await program.account.taskAllowlist.fetch(allowlistPda(task.task));
+      assert.equal(allowlist.entries.length, 2);
+
+      await expectError(assignTask(task, unlisted), "AgentNotAllowlisted");
+      await assignTask(task, byOwner);
+
+      // Once assigned the allowlist is only reclaimed; the task stays private.
+      await removeTaskAllowlist(task);
+      assert.isNull(await provider.connection.getAccountInfo(allowlistPda(task.task)));
+      taskAccount = await program.account.task.fetch(task.task);
+      assert.isTrue(taskAccount.isPrivate);
+    });
+
+    it("Admits agents proven under a Merkle root", async () => {
+      const task = await createTask(creator, 1_000_000);
+      const leaves = [
+        agentLeaf(listed.agentId),
+        ownerLeaf(byOwner.owner.publicKey),
+        agentLeaf(new anchor.BN(1_000_000)),
+        agentLeaf(new anchor.BN(1_000_001)),
+      ];
+      const left = hashPair(leaves[0], leaves[1]);
+      const right = hashPair(leaves[2], leaves[3]);
+      const root = hashPair(left, right);
+      await setTaskAllowlist(task, [], root);
+
+      const proof = [leaves[1], right].map((node) => Array.from(node));
+      await expectError(assignTask(task, unlisted, creator, proof), "AgentNotAllowlisted");
+      await expectError(assignTask(task, listed, creator, []), "AgentNotAllowlisted");
+      await assignTask(task, listed, creator, proof);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.equal(taskAccount.agentId.toString(), listed.agentId.toString());
+    });
+
+    it("Opens a pending task back up when its allowlist is removed", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await setTaskAllowlist(task, [agentEntry(listed)]);
+      await expectError(assignTask(task, unlisted), "AgentNotAllowlisted");
+
+      await removeTaskAllowlist(task);
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.isFalse(taskAccount.isPrivate);
+      await assignTask(task, unlisted);
+    });
+
+    it("Returns the allowlist rent to the creator when a delegate removes it", async () => {
+      const task = await createTask(creator, 1_000_000);
+      await program.methods
+        .setTaskDelegate(outsider.publicKey)
+        .accounts({ task: task.task, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await setTaskAllowlist(task, [agentEntry(listed)]);
+
+      const rent = await balance(allowlistPda(task.task));
+      const creatorBefore = await balance(creator.publicKey);
+      await removeTaskAllowlist(task, outsider);
+      assert.equal((await balance(creator.publicKey)) - creatorBefore, rent);
+    });
+  });
+
+  describe("result attestation and ownership transfer", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+    let worker: TestAgent;
+    const attestationKey = anchor.web3.Keypair.generate();
+    const content = Buffer.from("result content");
+    const contentHash = Array.from(sha256(content));
+
+    const setAttestationKey = (agent: TestAgent, key: anchor.web3.PublicKey | null, signer = agent.owner) =>
+      program.methods
+        .setAttestationKey(key)
+        .accounts({ agent: agent.agent, owner: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const attest = (task: TestTask, signer: anchor.web3.Keypair) =>
+      anchor.web3.Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message: Buffer.concat([task.task.toBuffer(), Buffer.from(contentHash)]),
+      });
+
+    const completeAttested = (task: TestTask, agent: TestAgent, preInstructions: anchor.web3.TransactionInstruction[]) =>
+      program.methods
+        .completeTask("https://result.uri", contentHash, { sha256: {} }, true)
+        .accounts({
+          state,
+          task: task.task,
+          agent: agent.agent,
+          rateLimit: rateLimitPda(agent.owner.publicKey),
+          authority: agent.owner.publicKey,
+          systemProgram,
+          instructions,
+        })
+        .preInstructions(preInstructions)
+        .signers([agent.owner])
+        .rpc();
+
+    const verifyResult = (task: TestTask, hash: number[]) =>
+      program.methods.verifyResult(hash).accounts({ task: task.task }).rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      worker = await registerAgent(await fundedKeypair());
+    });
+
+    it("Commits a result signed by the agent's attestation key", async () => {
+      await expectError(setAttestationKey(worker, attestationKey.publicKey, outsider), "Unauthorized");
+      await setAttestationKey(worker, attestationKey.publicKey);
+
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, worker);
+      await expectError(verifyResult(task, contentHash), "InvalidTaskStatus");
+
+      await expectError(completeAttested(task, worker, []), "InvalidAttestation");
+      await expectError(completeAttested(task, worker, [attest(task, outsider)]), "InvalidAttestation");
+      await completeAttested(task, worker, [attest(task, attestationKey)]);
+
+      const taskAccount = await program.account.task.fetch(task.task);
+      assert.deepEqual(taskAccount.status, { submitted: {} });
+      assert.isTrue(taskAccount.resultAttested);
+      assert.deepEqual(taskAccount.resultHash, contentHash);
+
+      await verifyResult(task, contentHash);
+      await expectError(verifyResult(task, Array.from(sha256(Buffer.from("other content")))), "ResultHashMismatch");
+    });
+
+    it("Hands an agent to its new owner without the previous owner's keys", async () => {
+      const newOwner = await fundedKeypair();
+      await program.methods
+        .setAgentOperator(outsider.publicKey)
+        .accounts({ agent: worker.agent, owner: worker.owner.publicKey })
+        .signers([worker.owner])
+        .rpc();
+      await setAttestationKey(worker, attestationKey.publicKey);
+
+      const transfer = (signer: anchor.web3.Keypair) =>
+        program.methods
+          .transferAgentOwnership(newOwner.publicKey)
+          .accounts({ agent: worker.agent, owner: signer.publicKey })
+          .signers([signer])
+          .rpc();
+      const accept = (signer: anchor.web3.Keypair) =>
+        program.methods
+          .acceptAgentOwnership()
+          .accounts({ agent: worker.agent, newOwner: signer.publicKey })
+          .signers([signer])
+          .rpc();
+
+      await expectError(transfer(outsider), "Unauthorized");
+      await transfer(worker.owner);
+      let agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.pendingOwner.toString(), newOwner.publicKey.toString());
+
+      await expectError(accept(outsider), "Unauthorized");
+      await accept(newOwner);
+
+      agentAccount = await program.account.agent.fetch(worker.agent);
+      assert.equal(agentAccount.owner.toString(), newOwner.publicKey.toString());
+      assert.isNull(agentAccount.pendingOwner);
+      assert.isNull(agentAccount.operator);
+      assert.isNull(agentAccount.attestationKey);
+    });
+  });
+
+  describe("marketplace fees", () => {
+    let outsider: anchor.web3.Keypair;
+
+    const setMarketplaceFee = (fee: number, signer: anchor.web3.Keypair | null = null) => {
+      const builder = program.methods
+        .setMarketplaceFee(fee)
+        .accounts({ marketplace, authority: signer ? signer.publicKey : wallet });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    const withdrawFees = (amount: number, signer: anchor.web3.Keypair | null = null) => {
+      const builder = program.methods
+        .withdrawMarketplaceFees(new anchor.BN(amount))
+        .accounts({
+          marketplace,
+          treasury: marketplaceTreasury,
+          destination: userTokenAccount,
+          authority: signer ? signer.publicKey : wallet,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    before(async () => {
+      outsider = await fundedKeypair();
+      await ensureMarketplace();
+    });
+
+    after(async () => {
+      await setMarketplaceFee(200);
+    });
+
+    it("Initializes the marketplace with a treasury owned by its PDA", async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      assert.equal(marketplaceAccount.authority.toString(), wallet.toString());
+      assert.equal(marketplaceAccount.paymentMint.toString(), mint.publicKey.toString());
+      assert.isTrue(marketplaceAccount.isActive);
+
+      const treasury = await getAccount(provider.connection, marketplaceTreasury);
+      assert.equal(treasury.owner.toString(), marketplace.toString());
+      assert.equal(treasury.mint.toString(), mint.publicKey.toString());
+    });
+
+    it("Only lets the marketplace authority set the fee", async () => {
+      await expectError(setMarketplaceFee(500, outsider), "Unauthorized");
+      await expectError(setMarketplaceFee(10001), "InvalidFee");
+      await setMarketplaceFee(500);
+
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      assert.equal(marketplaceAccount.feePercentage, 500);
+    });
+
+    it("Routes the fee to the treasury and the rest to the seller on purchase", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const buyer = await fundedKeypair();
+      const listing = await createListing(seller, 1_000_000);
+      await fundTokens(buyer.publicKey, 1_000_000);
+      const sellerTokenAccount = await fundTokens(seller.owner.publicKey, 0);
+
+      const treasuryBefore = await tokenBalance(marketplaceTreasury);
+      const { totalVolume } = await program.account.marketplace.fetch(marketplace);
+      await purchaseListing(listing, seller, buyer);
+
+      assert.equal(await tokenBalance(sellerTokenAccount), 950_000);
+      assert.equal((await tokenBalance(marketplaceTreasury)) - treasuryBefore, 50_000);
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      assert.equal(marketplaceAccount.totalVolume.sub(totalVolume).toNumber(), 1_000_000);
+    });
+
+    it("Only lets the marketplace authority withdraw collected fees", async () => {
+      await expectError(withdrawFees(50_000, outsider), "Unauthorized");
+
+      const walletBefore = await tokenBalance(userTokenAccount);
+      await withdrawFees(50_000);
+      assert.equal((await tokenBalance(userTokenAccount)) - walletBefore, 50_000);
+    });
+  });
+
+  describe("agent sales", () => {
+    let outsider: anchor.web3.Keypair;
+
+    before(async () => {
+      outsider = await fundedKeypair();
+      await ensureMarketplace();
+    });
+
+    it("Only lets the owner list an agent that is free of tasks", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const other = await registerAgent(await fundedKeypair());
+
+      await expectError(createListing({ ...seller, owner: outsider }, 1_000_000), "Unauthorized");
+      await expectError(createListing({ ...seller, agentId: other.agentId }, 1_000_000), "AgentIdMismatch");
+
+      const task = await createTask(outsider, 1_000_000);
+      await assignTask(task, other);
+      await expectError(createListing(other, 1_000_000), "AgentHasOpenTasks");
+    });
+
+    it("Locks a listed agent until it is sold", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      await createListing(seller, 1_000_000);
+
+      const agentAccount = await program.account.agent.fetch(seller.agent);
+      assert.isTrue(agentAccount.isListed);
+
+      await expectError(createListing(seller, 2_000_000), "AgentListed");
+      await expectError(
+        program.methods
+          .setAgentActive(false)
+          .accounts({ agent: seller.agent, owner: seller.owner.publicKey })
+          .signers([seller.owner])
+          .rpc(),
+        "AgentListed"
+      );
+      await expectError(
+        program.methods
+          .transferAgentOwnership(outsider.publicKey)
+          .accounts({ agent: seller.agent, owner: seller.owner.publicKey })
+          .signers([seller.owner])
+          .rpc(),
+        "AgentListed"
+      );
+    });
+
+    it("Hands the agent to the buyer on purchase", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const buyer = await fundedKeypair();
+      await program.methods
+        .setAgentOperator(outsider.publicKey)
+        .accounts({ agent: seller.agent, owner: seller.owner.publicKey })
+        .signers([seller.owner])
+        .rpc();
+      const listing = await createListing(seller, 1_000_000);
+      await fundTokens(buyer.publicKey, 1_000_000);
+
+      await purchaseListing(listing, seller, buyer);
+
+      const agentAccount = await program.account.agent.fetch(seller.agent);
+      assert.equal(agentAccount.owner.toString(), buyer.publicKey.toString());
+      assert.isFalse(agentAccount.isListed);
+      assert.isNull(agentAccount.operator);
+      const listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { sold: {} });
+
+      // The seller no longer owns the agent, so the sold listing cannot be bought again.
+      await fundTokens(outsider.publicKey, 1_000_000);
+      await expectError(purchaseListing(listing, seller, outsider), "Unauthorized");
+    });
+  });
+
+  describe("agent rentals", () => {
+    let creator: anchor.web3.Keypair;
+    let outsider: anchor.web3.Keypair;
+
+    const rentalPda = (listing: anchor.web3.PublicKey) => pda(Buffer.from("rental"), listing.toBuffer());
+
+    const createRentalListing = async (agent: TestAgent, period: number, maxPeriods: number) => {
+      const listing = await nextListingPda();
+      await program.methods
+        .createRentalListing(agent.agentId, new anchor.BN(100_000), new anchor.BN(period), maxPeriods, "Agent for rent")
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          agent: agent.agent,
+          seller: agent.owner.publicKey,
+          systemProgram,
+        })
+        .signers([agent.owner])
+        .rpc();
+      return listing;
+    };
+
+    const rentAgent = async (
+      listing: anchor.web3.PublicKey,
+      agent: TestAgent,
+      renter: anchor.web3.Keypair,
+      periods: number
+    ) =>
+      program.methods
+        .rentAgent(periods)
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          agent: agent.agent,
+          rental: rentalPda(listing),
+          renter: renter.publicKey,
+          renterTokenAccount: await fundTokens(renter.publicKey, 0),
+          ownerTokenAccount: await fundTokens(agent.owner.publicKey, 0),
+          treasury: marketplaceTreasury,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram,
+        })
+        .signers([renter])
+        .rpc();
+
+    const expireRental = (listing: anchor.web3.PublicKey, agent: TestAgent, renter: anchor.web3.PublicKey) =>
+      program.methods
+        .expireRental()
+        .accounts({ listing, agent: agent.agent, rental: rentalPda(listing), renter })
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      outsider = await fundedKeypair();
+      await ensureMarketplace();
+    });
+
+    it("Only lets the marketplace authority set the rental fee", async () => {
+      await expectError(
+        program.methods
+          .setRentalFee(500)
+          .accounts({ marketplace, authority: outsider.publicKey })
+          .signers([outsider])
+          .rpc(),
+        "Unauthorized"
+      );
+      await expectError(
+        program.methods.setRentalFee(10001).accounts({ marketplace, authority: wallet }).rpc(),
+        "InvalidFee"
+      );
+    });
+
+    it("Lets a renter operate the agent until the rental expires", async () => {
+      const owner = await registerAgent(await fundedKeypair());
+      const renter = await fundedKeypair();
+      await program.methods
+        .setAgentOperator(outsider.publicKey)
+        .accounts({ agent: owner.agent, owner: owner.owner.publicKey })
+        .signers([owner.owner])
+        .rpc();
+
+      await expectError(createRentalListing(owner, 0, 2), "InvalidRentalTerms");
+      const listing = await createRentalListing(owner, 10, 2);
+      await fundTokens(renter.publicKey, 1_000_000);
+
+      await expectError(rentAgent(listing, owner, renter, 3), "RentalTooLong");
+      await expectError(rentAgent(listing, owner, renter, 0), "InvalidRentalTerms");
+
+      const ownerTokenAccount = await fundTokens(owner.owner.publicKey, 0);
+      const treasuryBefore = await tokenBalance(marketplaceTreasury);
+      await rentAgent(listing, owner, renter, 1);
+      assert.equal(await tokenBalance(ownerTokenAccount), 98_000);
+      assert.equal((await tokenBalance(marketplaceTreasury)) - treasuryBefore, 2_000);
+
+      const rental = await program.account.agentRental.fetch(rentalPda(listing));
+      let agentAccount = await program.account.agent.fetch(owner.agent);
+      assert.equal(agentAccount.operator.toString(), renter.publicKey.toString());
+      assert.equal(agentAccount.rentedUntil.toNumber(), rental.expiresAt.toNumber());
+      let listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { rented: {} });
+
+      await expectError(
+        program.methods
+          .setAgentOperator(null)
+          .accounts({ agent: owner.agent, owner: owner.owner.publicKey })
+          .signers([owner.owner])
+          .rpc(),
+        "AgentRented"
+      );
+
+      // The renter works a task as the agent's operator during the rental.
+      const task = await createTask(creator, 1_000_000);
+      await assignTask(task, owner);
+      await completeTask(task, owner, renter);
+      const unfinished = await createTask(creator, 1_000_000);
+      await assignTask(unfinished, owner);
+
+      await expectError(expireRental(listing, owner, renter.publicKey), "RentalNotExpired");
+      await waitPast(rental.expiresAt.toNumber() - 1);
+      // The renter's rights end with the lease, before anyone expires the rental.
+      await expectError(completeTask(unfinished, owner, renter), "NotAgentOperator");
+      await completeTask(unfinished, owner);
+      await expireRental(listing, owner, renter.publicKey);
+
+      agentAccount = await program.account.agent.fetch(owner.agent);
+      assert.equal(agentAccount.operator.toString(), outsider.publicKey.toString());
+      assert.equal(agentAccount.rentedUntil.toNumber(), 0);
+      listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { active: {} });
+      assert.isNull(await provider.connection.getAccountInfo(rentalPda(listing)));
+
+      // The task taken on during the rental has to be settled before the agent is rented again.
+      const nextRenter = await fundedKeypair();
+      await fundTokens(nextRenter.publicKey, 1_000_000);
+      await expectError(rentAgent(listing, owner, nextRenter, 1), "AgentHasOpenTasks");
+      await approveResult(task, owner);
+      await approveResult(unfinished, owner);
+      await rentAgent(listing, owner, nextRenter, 1);
+    });
+  });
+
+  describe("listing management", () => {
+    let outsider: anchor.web3.Keypair;
+
+    const updateListingPrice = (listing: anchor.web3.PublicKey, price: number, seller: anchor.web3.Keypair) =>
+      program.methods
+        .updateListingPrice(new anchor.BN(price))
+        .accounts({ listing, seller: seller.publicKey })
+        .signers([seller])
+        .rpc();
+
+    const suspendListing = (listing: anchor.web3.PublicKey, signer: anchor.web3.Keypair | null = null) => {
+      const builder = program.methods
+        .suspendListing()
+        .accounts({ marketplace, listing, authority: signer ? signer.publicKey : wallet });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    const reinstateListing = (listing: anchor.web3.PublicKey, signer: anchor.web3.Keypair | null = null) => {
+      const builder = program.methods
+        .reinstateListing()
+        .accounts({ marketplace, listing, authority: signer ? signer.publicKey : wallet });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    const cancelListing = (listing: anchor.web3.PublicKey, agent: TestAgent, seller: anchor.web3.Keypair) =>
+      program.methods
+        .cancelListing()
+        .accounts({ listing, agent: agent.agent, seller: seller.publicKey })
+        .signers([seller])
+        .rpc();
+
+    before(async () => {
+      outsider = await fundedKeypair();
+      await ensureMarketplace();
+    });
+
+    it("Only lets the seller reprice a listing", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const listing = await createListing(seller, 1_000_000);
+
+      await expectError(updateListingPrice(listing, 2_000_000, outsider), "Unauthorized");
+      await expectError(updateListingPrice(listing, 0, seller.owner), "InvalidPrice");
+      await updateListingPrice(listing, 2_000_000, seller.owner);
+
+      const listingAccount = await program.account.agentListing.fetch(listing);
+      assert.equal(listingAccount.price.toNumber(), 2_000_000);
+    });
+
+    it("Lets the marketplace authority suspend and reinstate a listing", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const buyer = await fundedKeypair();
+      const listing = await createListing(seller, 1_000_000);
+      await fundTokens(buyer.publicKey, 1_000_000);
+
+      await expectError(suspendListing(listing, outsider), "Unauthorized");
+      await suspendListing(listing);
+      let listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { suspended: {} });
+
+      await expectError(purchaseListing(listing, seller, buyer), "InvalidListingStatus");
+      await expectError(suspendListing(listing), "InvalidListingStatus");
+      await expectError(reinstateListing(listing, outsider), "Unauthorized");
+
+      await reinstateListing(listing);
+      listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { active: {} });
+      await expectError(reinstateListing(listing), "InvalidListingStatus");
+
+      await purchaseListing(listing, seller, buyer);
+    });
+
+    it("Lets the seller cancel a listing and unlocks the agent", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const listing = await createListing(seller, 1_000_000);
+      await suspendListing(listing);
+
+      await expectError(cancelListing(listing, seller, outsider), "Unauthorized");
+
+      const sellerBefore = await balance(seller.owner.publicKey);
+      const listingLamports = await balance(listing);
+      await cancelListing(listing, seller, seller.owner);
+      assert.equal((await balance(seller.owner.publicKey)) - sellerBefore, listingLamports);
+      assert.isNull(await provider.connection.getAccountInfo(listing));
+
+      const agentAccount = await program.account.agent.fetch(seller.agent);
+      assert.isFalse(agentAccount.isListed);
+      const task = await createTask(outsider, 1_000_000);
+      await assignTask(task, seller);
+    });
+  });
+
+  describe("offers and auctions", () => {
+    let outsider: anchor.web3.Keypair;
+
+    const offerPda = (listing: anchor.web3.PublicKey, buyer: anchor.web3.PublicKey) =>
+      pda(Buffer.from("offer"), listing.toBuffer(), buyer.toBuffer());
+    const offerEscrowPda = (offer: anchor.web3.PublicKey) => pda(Buffer.from("offer_escrow"), offer.toBuffer());
+    const bidPda = (listing: anchor.web3.PublicKey, bidder: anchor.web3.PublicKey) =>
+      pda(Buffer.from("auction_bid"), listing.toBuffer(), bidder.toBuffer());
+    const bidEscrowPda = (bid: anchor.web3.PublicKey) => pda(Buffer.from("auction_bid_escrow"), bid.toBuffer());
+
+    const makeOffer = async (listing: anchor.web3.PublicKey, buyer: anchor.web3.Keypair, amount: number) => {
+      const offer = offerPda(listing, buyer.publicKey);
+      await program.methods
+        .makeOffer(new anchor.BN(amount))
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          offer,
+          escrow: offerEscrowPda(offer),
+          paymentMint: mint.publicKey,
+          buyer: buyer.publicKey,
+          buyerTokenAccount: await fundTokens(buyer.publicKey, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram,
+        })
+        .signers([buyer])
+        .rpc();
+      return offer;
+    };
+
+    const cancelOffer = async (offer: anchor.web3.PublicKey, buyer: anchor.web3.Keypair) =>
+      program.methods
+        .cancelOffer()
+        .accounts({
+          marketplace,
+          offer,
+          escrow: offerEscrowPda(offer),
+          buyer: buyer.publicKey,
+          buyerTokenAccount: await fundTokens(buyer.publicKey, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+    const acceptOffer = async (
+      listing: anchor.web3.PublicKey,
+      agent: TestAgent,
+      offer: anchor.web3.PublicKey,
+      buyer: anchor.web3.PublicKey,
+      seller: anchor.web3.Keypair = agent.owner
+    ) =>
+      program.methods
+        .acceptOffer()
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          agent: agent.agent,
+          offer,
+          escrow: offerEscrowPda(offer),
+          buyer,
+          seller: seller.publicKey,
+          sellerTokenAccount: await fundTokens(agent.owner.publicKey, 0),
+          treasury: marketplaceTreasury,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+
+    const createAuction = async (agent: TestAgent, endsAt: number, extensionWindow = 0) => {
+      const listing = await nextListingPda();
+      await program.methods
+        .createAuctionListing(
+          agent.agentId,
+          new anchor.BN(500_000),
+          new anchor.BN(100_000),
+          new anchor.BN(endsAt),
+          new anchor.BN(extensionWindow),
+          "Agent at auction"
+        )
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          agent: agent.agent,
+          seller: agent.owner.publicKey,
+          systemProgram,
+        })
+        .signers([agent.owner])
+        .rpc();
+      return listing;
+    };
+
+    const placeBid = async (listing: anchor.web3.PublicKey, bidder: anchor.web3.Keypair, amount: number) => {
+      const bid = bidPda(listing, bidder.publicKey);
+      return program.methods
+        .placeAuctionBid(new anchor.BN(amount))
+        .accounts({
+          state,
+          marketplace,
+          listing,
+          bid,
+          escrow: bidEscrowPda(bid),
+          paymentMint: mint.publicKey,
+          bidder: bidder.publicKey,
+          bidderTokenAccount: await fundTokens(bidder.publicKey, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram,
+        })
+        .signers([bidder])
+        .rpc();
+    };
+
+    const refundBid = async (listing: anchor.web3.PublicKey, bidder: anchor.web3.PublicKey) => {
+      const bid = bidPda(listing, bidder);
+      return program.methods
+        .refundAuctionBid()
+        .accounts({
+          marketplace,
+          listing,
+          bid,
+          escrow: bidEscrowPda(bid),
+          bidder,
+          bidderTokenAccount: await fundTokens(bidder, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    };
+
+    const settleAuction = async (listing: anchor.web3.PublicKey, agent: TestAgent, winner: anchor.web3.PublicKey) => {
+      const bid = bidPda(listing, winner);
+      return program.methods
+        .settleAuction()
+        .accounts({
+          marketplace,
+          listing,
+          agent: agent.agent,
+          bid,
+          escrow: bidEscrowPda(bid),
+          winner,
+          sellerTokenAccount: await fundTokens(agent.owner.publicKey, 0),
+          treasury: marketplaceTreasury,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    };
+
+    before(async () => {
+      outsider = await fundedKeypair();
+      await ensureMarketplace();
+    });
+
+    it("Escrows offers below the list price until the buyer withdraws them", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const buyer = await fundedKeypair();
+      const listing = await createListing(seller, 1_000_000);
+      const buyerTokenAccount = await fundTokens(buyer.publicKey, 1_000_000);
+
+      await expectError(makeOffer(listing, buyer, 1_000_000), "OfferTooHigh");
+      const offer = await makeOffer(listing, buyer, 600_000);
+      assert.equal(await tokenBalance(offerEscrowPda(offer)), 600_000);
+      assert.equal(await tokenBalance(buyerTokenAccount), 400_000);
+
+      await cancelOffer(offer, buyer);
+      assert.equal(await tokenBalance(buyerTokenAccount), 1_000_000);
+      assert.isNull(await provider.connection.getAccountInfo(offer));
+      assert.isNull(await provider.connection.getAccountInfo(offerEscrowPda(offer)));
+    });
+
+    it("Sells the agent to the buyer whose offer the seller accepts", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const buyer = await fundedKeypair();
+      const listing = await createListing(seller, 1_000_000);
+      await fundTokens(buyer.publicKey, 1_000_000);
+      const offer = await makeOffer(listing, buyer, 700_000);
+
+      await expectError(acceptOffer(listing, seller, offer, buyer.publicKey, outsider), "Unauthorized");
+
+      const sellerTokenAccount = await fundTokens(seller.owner.publicKey, 0);
+      const treasuryBefore = await tokenBalance(marketplaceTreasury);
+      await acceptOffer(listing, seller, offer, buyer.publicKey);
+
+      assert.equal(await tokenBalance(sellerTokenAccount), 686_000);
+      assert.equal((await tokenBalance(marketplaceTreasury)) - treasuryBefore, 14_000);
+      const agentAccount = await program.account.agent.fetch(seller.agent);
+      assert.equal(agentAccount.owner.toString(), buyer.publicKey.toString());
+      const listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { sold: {} });
+      assert.isNull(await provider.connection.getAccountInfo(offerEscrowPda(offer)));
+    });
+
+    it("Runs an English auction through to settlement", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const first = await fundedKeypair();
+      const second = await fundedKeypair();
+      const firstTokenAccount = await fundTokens(first.publicKey, 1_000_000);
+      const secondTokenAccount = await fundTokens(second.publicKey, 1_000_000);
+
+      await expectError(createAuction(seller, (await chainTime()) - 1), "InvalidAuctionTerms");
+      const listing = await createAuction(seller, (await chainTime()) + 12);
+
+      await expectError(placeBid(listing, seller.owner, 500_000), "SellerCannotBid");
+      await expectError(placeBid(listing, first, 400_000), "BidTooLow");
+      await placeBid(listing, first, 500_000);
+      await expectError(placeBid(listing, second, 550_000), "BidTooLow");
+      await placeBid(listing, second, 600_000);
+      // Raising a bid only escrows the difference.
+      await placeBid(listing, first, 700_000);
+      assert.equal(await tokenBalance(firstTokenAccount), 300_000);
+
+      let listingAccount = await program.account.agentListing.fetch(listing);
+      assert.equal(listingAccount.highestBid.toNumber(), 700_000);
+      assert.equal(listingAccount.highestBidder.toString(), first.publicKey.toString());
+      assert.equal(listingAccount.openBids, 2);
+
+      await expectError(refundBid(listing, first.publicKey), "BidIsWinning");
+      await refundBid(listing, second.publicKey);
+      assert.equal(await tokenBalance(secondTokenAccount), 1_000_000);
+      assert.isNull(await provider.connection.getAccountInfo(bidPda(listing, second.publicKey)));
+      listingAccount = await program.account.agentListing.fetch(listing);
+      assert.equal(listingAccount.openBids, 1);
+
+      await expectError(
+        program.methods
+          .suspendListing()
+          .accounts({ marketplace, listing, authority: wallet })
+          .rpc(),
+        "AuctionHasBids"
+      );
+      await expectError(
+        program.methods
+          .cancelListing()
+          .accounts({ listing, agent: seller.agent, seller: seller.owner.publicKey })
+          .signers([seller.owner])
+          .rpc(),
+        "AuctionHasBids"
+      );
+      const task = await createTask(outsider, 1_000_000);
+      await expectError(assignTask(task, seller), "AgentListed");
+
+      await expectError(settleAuction(listing, seller, first.publicKey), "AuctionNotEnded");
+      listingAccount = await program.account.agentListing.fetch(listing);
+      await waitPast(listingAccount.endsAt.toNumber());
+      await expectError(placeBid(listing, second, 800_000), "AuctionEnded");
+
+      const sellerTokenAccount = await fundTokens(seller.owner.publicKey, 0);
+      const treasuryBefore = await tokenBalance(marketplaceTreasury);
+      await settleAuction(listing, seller, first.publicKey);
+
+      assert.equal(await tokenBalance(sellerTokenAccount), 686_000);
+      assert.equal((await tokenBalance(marketplaceTreasury)) - treasuryBefore, 14_000);
+      const agentAccount = await program.account.agent.fetch(seller.agent);
+      assert.equal(agentAccount.owner.toString(), first.publicKey.toString());
+      assert.isFalse(agentAccount.isListed);
+      listingAccount = await program.account.agentListing.fetch(listing);
+      assert.deepEqual(listingAccount.status, { sold: {} });
+      assert.equal(listingAccount.openBids, 0);
+    });
+
+    it("Extends an auction when a bid lands inside the extension window", async () => {
+      const seller = await registerAgent(await fundedKeypair());
+      const bidder = await fundedKeypair();
+      await fundTokens(bidder.publicKey, 1_000_000);
+      const listing = await createAuction(seller, (await chainTime()) + 30, 120);
+
+      await placeBid(listing, bidder, 500_000);
+      const bid = await program.account.auctionBid.fetch(bidPda(listing, bidder.publicKey));
+      const listingAccount = await program.account.agentListing.fetch(listing);
+      assert.equal(listingAccount.endsAt.toNumber(), bid.placedAt.toNumber() + 120);
+    });
   });
 });